@@ -0,0 +1,22 @@
+use percent_encoding::percent_decode_str;
+
+use crate::browser::Browser;
+
+// Generates the gemtext for pages castor serves itself under the about:
+// scheme, returns None for urls that should be fetched over the network.
+pub fn page(browser: &Browser, url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+    if url.scheme() != "about" {
+        return None;
+    }
+
+    let query = url
+        .query()
+        .map(|query| percent_decode_str(query).decode_utf8_lossy().to_string());
+    let page = match url.path() {
+        "blank" => String::new(),
+        "bookmarks" => browser.profile.bookmarks.borrow().to_gemtext(query.as_deref()),
+        _ => format!("# Page not found\n\nCastor has no page called {url}\n"),
+    };
+    Some(page)
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    pub url: String,
+    pub title: String,
+    // empty for bookmarks that aren't in a folder
+    pub folder: String,
+    pub tags: Vec<String>,
+}
+
+// Bookmarks are kept in the castor config directory, one per line as
+// "<url>\t<folder>\t<comma separated tags>\t<title>"
+pub struct Bookmarks {
+    path: PathBuf,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn load() -> Result<Bookmarks> {
+        let path = crate::config_dir().join("bookmarks");
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let mut bookmarks = Vec::new();
+        for line in contents.lines() {
+            let mut fields = line.splitn(4, '\t');
+            let (url, folder, tags, title) =
+                match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                    (Some(url), Some(folder), Some(tags), Some(title)) => (url, folder, tags, title),
+                    _ => continue,
+                };
+            bookmarks.push(Bookmark {
+                url: url.to_string(),
+                title: title.to_string(),
+                folder: folder.to_string(),
+                tags: parse_tags(tags),
+            });
+        }
+
+        Ok(Bookmarks { path, bookmarks })
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.url == url)
+    }
+
+    // Adds bookmark, replacing any existing bookmark for the same url
+    pub fn set(&mut self, bookmark: Bookmark) -> Result<()> {
+        match self.bookmarks.iter_mut().find(|other| other.url == bookmark.url) {
+            Some(existing) => *existing = bookmark,
+            None => self.bookmarks.push(bookmark),
+        }
+        self.save()
+    }
+
+    pub fn remove(&mut self, url: &str) -> Result<()> {
+        self.bookmarks.retain(|bookmark| bookmark.url != url);
+        self.save()
+    }
+
+    pub fn folders(&self) -> Vec<&str> {
+        let mut folders: Vec<&str> = self
+            .bookmarks
+            .iter()
+            .map(|bookmark| bookmark.folder.as_str())
+            .filter(|folder| !folder.is_empty())
+            .collect();
+        folders.sort_unstable();
+        folders.dedup();
+        folders
+    }
+
+    // Generates the gemtext shown at about:bookmarks, optionally only listing
+    // the bookmarks with the given tag
+    pub fn to_gemtext(&self, tag: Option<&str>) -> String {
+        let mut gemtext = String::from("# Bookmarks\n\n");
+
+        let mut tags: Vec<&str> = self
+            .bookmarks
+            .iter()
+            .flat_map(|bookmark| bookmark.tags.iter().map(String::as_str))
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        if let Some(tag) = tag {
+            gemtext += &format!("Showing bookmarks tagged #{tag}\n");
+            gemtext += "=> about:bookmarks Show all bookmarks\n\n";
+        } else if !tags.is_empty() {
+            gemtext += "## Tags\n";
+            for tag in tags {
+                let query = percent_encoding::utf8_percent_encode(tag, percent_encoding::NON_ALPHANUMERIC);
+                gemtext += &format!("=> about:bookmarks?{query} #{tag}\n");
+            }
+            gemtext += "\n";
+        }
+
+        let shown: Vec<&Bookmark> = self
+            .bookmarks
+            .iter()
+            .filter(|bookmark| tag.map_or(true, |tag| bookmark.tags.iter().any(|other| other == tag)))
+            .collect();
+        if shown.is_empty() {
+            gemtext += "No bookmarks yet\n";
+            return gemtext;
+        }
+
+        let mut folders = vec![""];
+        folders.extend(self.folders());
+        for folder in folders {
+            let in_folder: Vec<&&Bookmark> = shown
+                .iter()
+                .filter(|bookmark| bookmark.folder == folder)
+                .collect();
+            if in_folder.is_empty() {
+                continue;
+            }
+            if !folder.is_empty() {
+                gemtext += &format!("## {folder}\n");
+            }
+            for bookmark in in_folder {
+                let title = if bookmark.title.is_empty() {
+                    &bookmark.url
+                } else {
+                    &bookmark.title
+                };
+                let tags: String = bookmark.tags.iter().map(|tag| format!(" #{tag}")).collect();
+                gemtext += &format!("=> {} {title}{tags}\n", bookmark.url);
+            }
+            gemtext += "\n";
+        }
+        gemtext
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        let mut contents = String::new();
+        for bookmark in &self.bookmarks {
+            contents += &format!(
+                "{}\t{}\t{}\t{}\n",
+                bookmark.url,
+                clean_field(&bookmark.folder),
+                bookmark.tags.join(","),
+                clean_field(&bookmark.title)
+            );
+        }
+        fs::write(&self.path, contents)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+// Splits a comma separated list of tags, tolerating spaces and leading #s
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(|tag| tag.trim().trim_start_matches('#').replace(char::is_whitespace, "-"))
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+// tabs and newlines would break the line based format
+fn clean_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
@@ -0,0 +1,691 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use glib::{clone, Continue, MainContext};
+use gtk::{gio, prelude::*, ApplicationWindow, Builder, Button, Entry, Label, Notebook, Spinner};
+use gtk4 as gtk;
+
+use crate::cancel::Cancel;
+use crate::client::{Progress, Response};
+use crate::find::FindBar;
+use crate::header::{Header, StatusCode};
+use crate::profile::{cache_capacity, Profile};
+use crate::tab::{LinkAction, Tab};
+use crate::settings::{self, Settings};
+use crate::shortcuts;
+use crate::{
+    about, apply_settings, bookmark_modal, build_ui, download, history_view, identities_view,
+    input, load_page, load_page_error_modal, page_info, preferences, resolve_url, save_modal,
+    show_cached_page, show_gemtext, Castor, LoadPageError, Loader, Page,
+};
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Back,
+    Forward,
+}
+
+// Window wide state shared by every tab
+pub struct Browser {
+    pub window: ApplicationWindow,
+    pub notebook: Notebook,
+    pub url_bar: Entry,
+    pub back_button: Button,
+    pub forward_button: Button,
+    pub bookmark_button: Button,
+    pub status_bar: Label,
+    spinner: Spinner,
+    // while the pointer is over a link the status bar shows where it goes
+    hovering: Cell<bool>,
+    pub find_bar: FindBar,
+    // what every window shares
+    pub profile: Rc<Profile>,
+    tabs: RefCell<Vec<Rc<Tab>>>,
+    // state of closed tabs, most recently closed last
+    closed_tabs: RefCell<Vec<Castor>>,
+}
+
+impl Browser {
+    pub fn new(builder: &Builder, profile: Rc<Profile>) -> Rc<Browser> {
+        profile.open_windows.set(profile.open_windows.get() + 1);
+        let browser = Rc::new(Browser {
+            window: builder.object("window").expect("Couldn't get window"),
+            notebook: builder.object("tabs").expect("Couldn't get tabs"),
+            url_bar: builder.object("url_bar").expect("Couldn't get url bar"),
+            back_button: builder.object("back_button").expect("Couldn't get back button"),
+            forward_button: builder.object("forward_button").expect("Couldn't get forward button"),
+            bookmark_button: builder.object("bookmark_button").expect("Couldn't get bookmark button"),
+            status_bar: builder.object("status_bar").expect("Couldn't get status bar"),
+            spinner: builder.object("spinner").expect("Couldn't get spinner"),
+            hovering: Cell::new(false),
+            find_bar: FindBar::new(builder),
+            profile,
+            tabs: RefCell::new(Vec::new()),
+            closed_tabs: RefCell::new(Vec::new()),
+        });
+
+        if let Some(app) = browser.window.application() {
+            shortcuts::apply(&app, &browser.profile.settings.borrow());
+        }
+
+        let weak = Rc::downgrade(&browser);
+        // switch-page is emitted before the current page changes, so listen for
+        // the property change instead
+        browser.notebook.connect_page_notify(clone!(@strong weak => move |_| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    browser.update_navigation(&tab);
+                    browser.find_again(&tab);
+                }
+            }
+        }));
+
+        // navigation the toolbar buttons also do, as actions so they can have
+        // keyboard shortcuts
+        let tab_actions: [(&str, fn(&Rc<Browser>, &Rc<Tab>)); 6] = [
+            ("back", |browser, tab| browser.go_back(tab)),
+            ("forward", |browser, tab| browser.go_forward(tab)),
+            ("refresh", |browser, tab| browser.refresh(tab)),
+            ("focus-url", |browser, _| {
+                browser.url_bar.grab_focus();
+            }),
+            ("link-numbers", |_, tab| tab.toggle_link_numbers()),
+            ("stop", |browser, tab| {
+                tab.stop_loading();
+                tab.status.replace(String::from("Stopped"));
+                browser.update_navigation(tab);
+            }),
+        ];
+        for (name, activate) in tab_actions {
+            let action = gio::SimpleAction::new(name, None);
+            action.connect_activate(clone!(@strong weak => move |_, _| {
+                if let Some(browser) = weak.upgrade() {
+                    if let Some(tab) = browser.current_tab() {
+                        activate(&browser, &tab);
+                    }
+                }
+            }));
+            browser.window.add_action(&action);
+        }
+
+        let new_tab = gio::SimpleAction::new("new-tab", None);
+        new_tab.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                let home_page = browser.profile.settings.borrow().home_page.clone();
+                browser.open_tab(Castor::with_url(home_page), true);
+            }
+        }));
+        browser.window.add_action(&new_tab);
+
+        let close_tab = gio::SimpleAction::new("close-tab", None);
+        close_tab.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    browser.close_tab(&tab);
+                }
+            }
+        }));
+        browser.window.add_action(&close_tab);
+
+        let reopen_closed_tab = gio::SimpleAction::new("reopen-closed-tab", None);
+        reopen_closed_tab.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.reopen_closed_tab();
+            }
+        }));
+        browser.window.add_action(&reopen_closed_tab);
+
+        let show_bookmarks = gio::SimpleAction::new("show-bookmarks", None);
+        show_bookmarks.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    browser.navigate(&tab, String::from("about:bookmarks"));
+                }
+            }
+        }));
+        browser.window.add_action(&show_bookmarks);
+
+        let bookmark_page = gio::SimpleAction::new("bookmark-page", None);
+        bookmark_page.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    let url = tab.state.borrow().current_url();
+                    browser.bookmark(&tab, url, tab.title());
+                }
+            }
+        }));
+        browser.window.add_action(&bookmark_page);
+
+        let show_history = gio::SimpleAction::new("show-history", None);
+        show_history.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                history_view::show(&browser);
+            }
+        }));
+        browser.window.add_action(&show_history);
+
+        let show_identities = gio::SimpleAction::new("show-identities", None);
+        show_identities.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                identities_view::show(&browser);
+            }
+        }));
+        browser.window.add_action(&show_identities);
+
+        let show_preferences = gio::SimpleAction::new("preferences", None);
+        show_preferences.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                preferences::show(&browser);
+            }
+        }));
+        browser.window.add_action(&show_preferences);
+
+        let view_source = gio::SimpleAction::new("view-source", None);
+        view_source.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    tab.toggle_source();
+                    browser.find_again(&tab);
+                }
+            }
+        }));
+        browser.window.add_action(&view_source);
+
+        let save_page = gio::SimpleAction::new("save-page", None);
+        save_page.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    browser.save_page(&tab);
+                }
+            }
+        }));
+        browser.window.add_action(&save_page);
+
+        let show_page_info = gio::SimpleAction::new("page-info", None);
+        show_page_info.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    page_info::show(&browser, &tab);
+                }
+            }
+        }));
+        browser.window.add_action(&show_page_info);
+
+        let find = gio::SimpleAction::new("find", None);
+        find.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.find_bar.open();
+            }
+        }));
+        browser.window.add_action(&find);
+        browser.connect_find_bar();
+
+        let clear_cache = gio::SimpleAction::new("clear-cache", None);
+        clear_cache.connect_activate(clone!(@strong weak => move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.clear_cache();
+            }
+        }));
+        browser.window.add_action(&clear_cache);
+
+        browser.window.connect_close_request(clone!(@strong weak => move |_| {
+            if let Some(browser) = weak.upgrade() {
+                let profile = &browser.profile;
+                profile.open_windows.set(profile.open_windows.get() - 1);
+                if profile.open_windows.get() == 0 {
+                    if let Err(err) = settings::save_session(&browser.session_urls()) {
+                        eprintln!("Failed to save session: {err:#}");
+                    }
+                }
+            }
+            gtk::Inhibit(false)
+        }));
+
+        browser
+    }
+
+    // Adds a tab showing state's current url and starts loading it
+    pub fn open_tab(self: &Rc<Self>, state: Castor, switch_to: bool) -> Rc<Tab> {
+        let (tab, link_rx) = Tab::new(state, &self.profile.tag_table);
+        let tab = Rc::new(tab);
+
+        let page = self.notebook.append_page(&tab.scroll, Some(&tab.tab_label));
+        self.notebook.set_tab_reorderable(&tab.scroll, true);
+        self.tabs.borrow_mut().push(tab.clone());
+
+        let weak = Rc::downgrade(self);
+        let weak_tab = Rc::downgrade(&tab);
+        tab.close_button.connect_clicked(clone!(@strong weak, @strong weak_tab => move |_| {
+            if let (Some(browser), Some(tab)) = (weak.upgrade(), weak_tab.upgrade()) {
+                browser.close_tab(&tab);
+            }
+        }));
+        link_rx.attach(None, move |action| {
+            let (browser, tab) = match (weak.upgrade(), weak_tab.upgrade()) {
+                (Some(browser), Some(tab)) => (browser, tab),
+                _ => return Continue(false),
+            };
+            // resolve relative links against the page they're on
+            let current_url = tab.state.borrow().current_url();
+            let resolve = |url: String| resolve_url(&current_url, &url).unwrap_or(url);
+            match action {
+                LinkAction::Follow(url) => browser.navigate(&tab, url),
+                LinkAction::OpenInNewTab(url) => {
+                    browser.open_tab(Castor::with_url(resolve(url)), false);
+                }
+                LinkAction::OpenInNewWindow(url) => browser.open_window(resolve(url)),
+                LinkAction::CopyAddress(url) => browser.window.clipboard().set_text(&resolve(url)),
+                LinkAction::Bookmark(url, title) => browser.bookmark(&tab, resolve(url), title),
+                LinkAction::Save(url) => browser.save_link(&tab, resolve(url)),
+                LinkAction::ViewHeader(url) => browser.view_header(resolve(url)),
+                LinkAction::Prompt(url, text) => browser.prompt(&tab, resolve(url), text),
+                LinkAction::Hover(Some(url)) => {
+                    browser.hovering.set(true);
+                    browser.status_bar.set_text(&resolve(url));
+                }
+                LinkAction::Hover(None) => {
+                    browser.hovering.set(false);
+                    if let Some(tab) = browser.current_tab() {
+                        browser.show_status(&tab);
+                    }
+                }
+            }
+            Continue(true)
+        });
+
+        if switch_to {
+            self.notebook.set_current_page(Some(page));
+        }
+        self.refresh(&tab);
+        tab
+    }
+
+    fn connect_find_bar(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        let find_bar = &self.find_bar;
+        let search = clone!(@strong weak => move || {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    browser.find_bar.search(&tab.text_view);
+                }
+            }
+        });
+        let step = clone!(@strong weak => move |forward: bool| {
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    browser.find_bar.step(&tab.text_view, forward);
+                }
+            }
+        });
+
+        find_bar.entry.connect_search_changed(clone!(@strong search => move |_| search()));
+        find_bar.match_case.connect_toggled(clone!(@strong search => move |_| search()));
+        find_bar.whole_word.connect_toggled(move |_| search());
+        find_bar.entry.connect_activate(clone!(@strong step => move |_| step(true)));
+        find_bar.entry.connect_next_match(clone!(@strong step => move |_| step(true)));
+        find_bar.entry.connect_previous_match(clone!(@strong step => move |_| step(false)));
+        find_bar.next.connect_clicked(clone!(@strong step => move |_| step(true)));
+        find_bar.previous.connect_clicked(move |_| step(false));
+        find_bar.bar.connect_search_mode_enabled_notify(move |bar| {
+            if bar.is_search_mode() {
+                return;
+            }
+            if let Some(browser) = weak.upgrade() {
+                if let Some(tab) = browser.current_tab() {
+                    browser.find_bar.clear(&tab.text_view);
+                }
+            }
+        });
+    }
+
+    // Searches tab again if the find bar is open, for when the page changed
+    // underneath it
+    fn find_again(&self, tab: &Tab) {
+        if self.find_bar.is_open() {
+            self.find_bar.search(&tab.text_view);
+        }
+    }
+
+    // Opens url in a window of its own
+    pub fn open_window(&self, url: String) {
+        let app = match self.window.application() {
+            Some(app) => app,
+            None => return,
+        };
+        build_ui(&app, &self.profile, Some(url)).show();
+    }
+
+    pub fn close_tab(&self, tab: &Rc<Tab>) {
+        let page = match self.notebook.page_num(&tab.scroll) {
+            Some(page) => page,
+            None => return,
+        };
+        // a load left going would carry on asking questions about a tab
+        // that's gone
+        tab.stop_loading();
+        self.notebook.remove_page(Some(page));
+        self.tabs.borrow_mut().retain(|other| !Rc::ptr_eq(other, tab));
+        self.closed_tabs.borrow_mut().push(tab.state.borrow().clone());
+
+        if self.notebook.n_pages() == 0 {
+            self.window.close();
+        }
+    }
+
+    pub fn reopen_closed_tab(self: &Rc<Self>) {
+        let state = self.closed_tabs.borrow_mut().pop();
+        if let Some(state) = state {
+            self.open_tab(state, true);
+        }
+    }
+
+    // Applies a change to the settings right away and saves them
+    pub fn update_settings(&self, change: &dyn Fn(&mut Settings)) {
+        let profile = &self.profile;
+        let mut settings = profile.settings.borrow_mut();
+        change(&mut settings);
+        apply_settings(&profile.tag_table, &settings);
+        if let Some(app) = self.window.application() {
+            shortcuts::apply(&app, &settings);
+        }
+        profile.cache.borrow_mut().set_capacity(cache_capacity(&settings));
+        profile.client.set_timeout(Duration::from_secs(settings.request_timeout));
+        if let Err(err) = settings.save() {
+            eprintln!("Failed to save settings: {err:#}");
+        }
+    }
+
+    pub fn clear_cache(&self) {
+        self.profile.cache.borrow_mut().clear();
+    }
+
+    // Current urls of the open tabs, in the order they're shown in
+    pub fn session_urls(&self) -> Vec<String> {
+        (0..self.notebook.n_pages())
+            .filter_map(|page| self.notebook.nth_page(Some(page)))
+            .filter_map(|page| self.tab_for_page(&page))
+            .map(|tab| tab.state.borrow().current_url())
+            .collect()
+    }
+
+    pub fn current_tab(&self) -> Option<Rc<Tab>> {
+        let page = self.notebook.nth_page(self.notebook.current_page())?;
+        self.tab_for_page(&page)
+    }
+
+    fn tab_for_page(&self, page: &gtk::Widget) -> Option<Rc<Tab>> {
+        self.tabs
+            .borrow()
+            .iter()
+            .find(|tab| tab.scroll.upcast_ref::<gtk::Widget>() == page)
+            .cloned()
+    }
+
+    // Syncs the url bar and back/forward buttons with tab, if it's the one
+    // being shown
+    pub fn update_navigation(&self, tab: &Rc<Tab>) {
+        tab.update_title();
+        if self.notebook.page_num(&tab.scroll) != self.notebook.current_page() {
+            return;
+        }
+        let current_url = tab.state.borrow().current_url();
+        self.url_bar.set_text(&current_url);
+        self.back_button.set_sensitive(tab.can_go_back());
+        self.forward_button.set_sensitive(tab.can_go_forward());
+        let bookmarked = self.profile.bookmarks.borrow().get(&current_url).is_some();
+        self.bookmark_button.set_label(if bookmarked { "★" } else { "☆" });
+        if let Some(stop) = self
+            .window
+            .lookup_action("stop")
+            .and_then(|action| action.downcast::<gio::SimpleAction>().ok())
+        {
+            stop.set_enabled(tab.is_loading());
+        }
+        self.show_status(tab);
+    }
+
+    // Shows how tab's request is getting on, if it's the one being shown
+    // Shows progress in tab's status, as long as cancel's load is still the
+    // one in progress. One that has been stopped or replaced may still report
+    // before it notices.
+    fn report_progress(&self, tab: &Rc<Tab>, cancel: &Cancel, progress: Progress) {
+        if tab.is_current_load(cancel) {
+            tab.status.replace(progress.to_string());
+            self.show_status(tab);
+        }
+    }
+
+    fn show_status(&self, tab: &Rc<Tab>) {
+        if self.notebook.page_num(&tab.scroll) != self.notebook.current_page() {
+            return;
+        }
+        self.spinner.set_spinning(tab.is_loading());
+        if !self.hovering.get() {
+            self.status_bar.set_text(&tab.status.borrow());
+        }
+    }
+
+    // Lets the user add, edit or remove the bookmark for url
+    pub fn bookmark(self: &Rc<Self>, tab: &Rc<Tab>, url: String, title: String) {
+        let browser = self.clone();
+        let tab = tab.clone();
+        MainContext::default().spawn_local(async move {
+            bookmark_modal(&browser.window, &browser.profile.bookmarks, url, title).await;
+            browser.update_navigation(&tab);
+        });
+    }
+
+    // Downloads url straight to disk without showing it. It goes through tab
+    // like a load would, so the status bar follows it and stopping the tab
+    // cancels it, but the page on show stays as it is.
+    pub fn save_link(self: &Rc<Self>, tab: &Rc<Tab>, url: String) {
+        let browser = self.clone();
+        let tab = tab.clone();
+        MainContext::default().spawn_local(async move {
+            let cancel = tab.start_loading();
+            browser.update_navigation(&tab);
+            let progress = |progress: Progress| browser.report_progress(&tab, &cancel, progress);
+            let loader = Loader {
+                client: &browser.profile.client,
+                settings: &browser.profile.settings,
+                cancel: &cancel,
+                progress: &progress,
+                text_view: &tab.text_view,
+                window: &browser.window,
+            };
+            download(&loader, url, Vec::new()).await;
+            tab.finish_loading(&cancel);
+            browser.update_navigation(&tab);
+        });
+    }
+
+    // Asks for the input a prompt link wants and then follows it with that
+    pub fn prompt(self: &Rc<Self>, tab: &Rc<Tab>, url: String, text: String) {
+        let browser = self.clone();
+        let tab = tab.clone();
+        MainContext::default().spawn_local(async move {
+            if let Some(url) = input::input_modal(&browser.window, &url, &text, false).await {
+                browser.navigate(&tab, url);
+            }
+        });
+    }
+
+    // Shows the header url responds with, without following it anywhere
+    pub fn view_header(self: &Rc<Self>, url: String) {
+        let browser = self.clone();
+        MainContext::default().spawn_local(async move {
+            let text = match browser.profile.client.header(url.clone()).await {
+                Ok(header) => header.to_string(),
+                Err(err) => format!("Request failed: {err}"),
+            };
+            let dialog = gtk::MessageDialog::builder()
+                .transient_for(&browser.window)
+                .modal(true)
+                .buttons(gtk::ButtonsType::Ok)
+                .text(&url)
+                .secondary_text(&text)
+                .build();
+            dialog.run_future().await;
+            dialog.close();
+        });
+    }
+
+    pub fn save_page(self: &Rc<Self>, tab: &Rc<Tab>) {
+        let (url, body) = match &*tab.page.borrow() {
+            Some(page) => (page.url.clone(), page.response.body.clone().unwrap_or_default()),
+            None => return,
+        };
+        let browser = self.clone();
+        MainContext::default().spawn_local(async move {
+            if let Err(err) = save_modal(&browser.window, &url, &body).await {
+                load_page_error_modal(&browser.window, LoadPageError::Download(err)).await;
+            }
+        });
+    }
+
+    // Loads url in tab as a new entry in its history. Only the url the page
+    // finally loaded from is recorded, failed or cancelled loads leave the
+    // history alone.
+    pub fn navigate(self: &Rc<Self>, tab: &Rc<Tab>, url: String) {
+        tab.save_scroll();
+        let browser = self.clone();
+        let tab = tab.clone();
+        MainContext::default().spawn_local(async move {
+            let current_url = tab.state.borrow().current_url();
+            if let Some(page) = browser.load(&tab, current_url, url, false).await {
+                tab.state.borrow_mut().history.push(page.url, page.title);
+            }
+            browser.update_navigation(&tab);
+        });
+    }
+
+    pub fn go_back(self: &Rc<Self>, tab: &Rc<Tab>) {
+        self.load_history_entry(tab, Direction::Back);
+    }
+
+    pub fn go_forward(self: &Rc<Self>, tab: &Rc<Tab>) {
+        self.load_history_entry(tab, Direction::Forward);
+    }
+
+    // Moves through the tab's history once the entry in direction loaded
+    fn load_history_entry(self: &Rc<Self>, tab: &Rc<Tab>, direction: Direction) {
+        let url = {
+            let state = tab.state.borrow();
+            let entry = match direction {
+                Direction::Back => state.history.peek_back(),
+                Direction::Forward => state.history.peek_forward(),
+            };
+            match entry {
+                Some(entry) => entry.url.clone(),
+                None => return,
+            }
+        };
+        tab.save_scroll();
+
+        let browser = self.clone();
+        let tab = tab.clone();
+        MainContext::default().spawn_local(async move {
+            let current_url = tab.state.borrow().current_url();
+            // going back and forward shows the page as it was, if it's cached
+            if let Some(page) = browser.load(&tab, current_url, url, true).await {
+                {
+                    let mut state = tab.state.borrow_mut();
+                    match direction {
+                        Direction::Back => state.history.back(),
+                        Direction::Forward => state.history.forward(),
+                    };
+                    state.history.replace(page.url, page.title);
+                }
+                tab.restore_scroll();
+            }
+            browser.update_navigation(&tab);
+        });
+    }
+
+    pub fn refresh(self: &Rc<Self>, tab: &Rc<Tab>) {
+        tab.save_scroll();
+        self.update_navigation(tab);
+        let browser = self.clone();
+        let tab = tab.clone();
+        MainContext::default().spawn_local(async move {
+            let current_url = tab.state.borrow().current_url();
+            if let Some(page) = browser.load(&tab, current_url.clone(), current_url, false).await {
+                tab.state.borrow_mut().history.replace(page.url, page.title);
+                tab.restore_scroll();
+            }
+            browser.update_navigation(&tab);
+        });
+    }
+
+    // Loads url into tab and records the visit in the global history, from the
+    // cache if use_cache is set and it's there.
+    // Starting a load cancels the one the tab already had going.
+    async fn load(
+        &self,
+        tab: &Rc<Tab>,
+        current_url: String,
+        url: String,
+        use_cache: bool,
+    ) -> Option<Page> {
+        let cancel = tab.start_loading();
+        self.update_navigation(tab);
+        // cached pages are shown straight away, without a request
+        let cached = match use_cache {
+            true => self.profile.cache.borrow_mut().get(&url).cloned(),
+            false => None,
+        }
+        .filter(|page| show_cached_page(page, &tab.text_view));
+        let page = match about::page(self, &url) {
+            Some(source) => Some(Page {
+                title: show_gemtext(&source, &tab.text_view),
+                url,
+                redirects: Vec::new(),
+                // pages castor makes itself are treated like any other
+                // gemtext page when viewing or saving them
+                response: Response {
+                    header: Header {
+                        status: StatusCode::Success,
+                        meta: String::from("text/gemini"),
+                    },
+                    body: Some(source.into_bytes()),
+                    certificate: None,
+                },
+            }),
+            None if cached.is_some() => {
+                tab.status.replace(String::from("Loaded from cache"));
+                cached
+            }
+            None => {
+                let progress = |progress: Progress| self.report_progress(tab, &cancel, progress);
+                let loader = Loader {
+                    client: &self.profile.client,
+                    settings: &self.profile.settings,
+                    cancel: &cancel,
+                    progress: &progress,
+                    text_view: &tab.text_view,
+                    window: &self.window,
+                };
+                let page = load_page(&loader, current_url, url, Vec::new()).await;
+                if let Some(page) = &page {
+                    let size = page.response.body.as_ref().map_or(0, Vec::len);
+                    self.profile.cache.borrow_mut().insert(page.url.clone(), page.clone(), size);
+                }
+                page
+            }
+        };
+        tab.finish_loading(&cancel);
+
+        if let Some(page) = &page {
+            let title = page.title.as_deref().unwrap_or_default();
+            let history = &self.profile.history;
+            if let Err(err) = history.borrow_mut().record(&page.url, title) {
+                eprintln!("Failed to record history: {err:#}");
+            }
+            tab.mark_visited_links(&page.url, &history.borrow());
+            tab.set_page(page.clone());
+            self.find_again(tab);
+        }
+        page
+    }
+}
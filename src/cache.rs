@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+
+// Recently loaded pages by url, so going back and forward doesn't have to
+// fetch them again. Once the entries add up to more than the capacity the
+// least recently used ones are dropped.
+pub struct Cache<T> {
+    // least recently used first
+    entries: VecDeque<Entry<T>>,
+    // bytes the entries may add up to
+    capacity: usize,
+    size: usize,
+}
+
+struct Entry<T> {
+    url: String,
+    value: T,
+    size: usize,
+}
+
+impl<T> Cache<T> {
+    pub fn new(capacity: usize) -> Cache<T> {
+        Cache {
+            entries: VecDeque::new(),
+            capacity,
+            size: 0,
+        }
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<&T> {
+        let index = self.entries.iter().position(|entry| entry.url == url)?;
+        let entry = self.entries.remove(index)?;
+        self.entries.push_back(entry);
+        self.entries.back().map(|entry| &entry.value)
+    }
+
+    // Adds value, which takes up size bytes, replacing anything already
+    // cached for url. Values bigger than the whole cache aren't kept.
+    pub fn insert(&mut self, url: String, value: T, size: usize) {
+        self.remove(&url);
+        if size > self.capacity {
+            return;
+        }
+        self.size += size;
+        self.entries.push_back(Entry { url, value, size });
+        self.shrink();
+    }
+
+    pub fn remove(&mut self, url: &str) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.url == url) {
+            if let Some(entry) = self.entries.remove(index) {
+                self.size -= entry.size;
+            }
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.shrink();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    fn shrink(&mut self) {
+        while self.size > self.capacity {
+            match self.entries.pop_front() {
+                Some(entry) => self.size -= entry.size,
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_least_recently_used() {
+        let mut cache = Cache::new(10);
+        cache.insert(String::from("a"), 1, 4);
+        cache.insert(String::from("b"), 2, 4);
+        assert_eq!(cache.get("a"), Some(&1));
+        cache.insert(String::from("c"), 3, 4);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.get("c"), Some(&3));
+    }
+
+    #[test]
+    fn replaces_and_skips_oversized() {
+        let mut cache = Cache::new(10);
+        cache.insert(String::from("a"), 1, 4);
+        cache.insert(String::from("a"), 2, 6);
+        assert_eq!(cache.get("a"), Some(&2));
+        cache.insert(String::from("b"), 3, 11);
+        assert_eq!(cache.get("b"), None);
+        cache.set_capacity(0);
+        assert_eq!(cache.get("a"), None);
+    }
+}
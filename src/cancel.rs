@@ -0,0 +1,65 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+// Lets a load be abandoned part way through. Cancelling drops whatever the
+// load was waiting on, which closes its connection.
+#[derive(Clone, Default)]
+pub struct Cancel {
+    inner: Rc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: Cell<bool>,
+    // wakes the future waiting in Cancel::run so it notices the cancellation
+    waker: RefCell<Option<Waker>>,
+}
+
+impl Cancel {
+    pub fn new() -> Cancel {
+        Cancel::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.set(true);
+        if let Some(waker) = self.inner.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+
+    // Runs future to completion, or returns None as soon as this is cancelled
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        Cancellable {
+            inner: &self.inner,
+            future: Box::pin(future),
+        }
+        .await
+    }
+}
+
+// Two handles are equal when they cancel the same load
+impl PartialEq for Cancel {
+    fn eq(&self, other: &Cancel) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+struct Cancellable<'a, F> {
+    inner: &'a Inner,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Cancellable<'_, F> {
+    type Output = Option<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.inner.cancelled.get() {
+            return Poll::Ready(None);
+        }
+        *self.inner.waker.borrow_mut() = Some(cx.waker().clone());
+        self.future.as_mut().poll(cx).map(Some)
+    }
+}
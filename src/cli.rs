@@ -0,0 +1,127 @@
+use std::io::Write;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::client::{Client, Response};
+use crate::header::StatusCode;
+use crate::mime::Mime;
+use crate::stream::{Line, Parser};
+use crate::{redirect_target, resolve_url};
+
+pub const USAGE: &str = "\
+Usage:
+    castor [URL]                  open URL, or the home page, in a window
+    castor --dump [--render] URL  print the body of URL to stdout
+
+Options:
+    --dump      fetch URL without opening a window and print the raw body
+    --render    with --dump, print gemtext as plain text instead of markup
+    -h, --help  show this message";
+
+pub enum Command {
+    Browse(Option<String>),
+    Dump { url: String, render: bool },
+    Help,
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut dump = false;
+    let mut render = false;
+    let mut url = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--dump" => dump = true,
+            "--render" => render = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ if url.is_some() => return Err(String::from("only one url can be given")),
+            _ => url = Some(normalize_url(&arg)),
+        }
+    }
+
+    if render && !dump {
+        return Err(String::from("--render can only be used with --dump"));
+    }
+    if dump {
+        let url = url.ok_or_else(|| String::from("--dump needs a url"))?;
+        return Ok(Command::Dump { url, render });
+    }
+    Ok(Command::Browse(url))
+}
+
+// Lets "example.org/page" stand in for "gemini://example.org/page"
+fn normalize_url(url: &str) -> String {
+    if url.contains("://") || url.starts_with("about:") {
+        url.to_string()
+    } else {
+        format!("gemini://{url}")
+    }
+}
+
+// Fetches url the same way the browser would, minus anything that needs
+// asking the user, and prints the body
+pub fn dump(url: &str, render: bool) -> Result<()> {
+    let client = Client::new().context("Failed to create gemini client")?;
+    let (url, response) = async_std::task::block_on(fetch(&client, url.to_string()))?;
+    let body = response.body.unwrap_or_default();
+
+    let mut stdout = std::io::stdout().lock();
+    let mime = Mime::parse(&response.header.meta);
+    if render && mime.essence == "text/gemini" {
+        let text = mime
+            .decode(&body)
+            .ok_or_else(|| anyhow!("Unsupported charset: {}", response.header.meta))?;
+        stdout.write_all(render_gemtext(Parser::for_url(&url), &text).as_bytes())?;
+    } else {
+        stdout.write_all(&body)?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// The response url finally led to, and the url it came from
+async fn fetch(client: &Client, url: String) -> Result<(String, Response)> {
+    let mut url = resolve_url(crate::DEFAULT_URL, &url)?;
+    let mut redirects = Vec::new();
+    loop {
+        let response = client.async_request(url.clone()).await?;
+        match response.header.status {
+            StatusCode::Success => return Ok((url, response)),
+            StatusCode::Redirect(_) => {
+                url = redirect_target(&url, &response.header.meta, &mut redirects)
+                    .map_err(|err| anyhow!("{err}"))?;
+                eprintln!("Redirected to {url}");
+            }
+            StatusCode::Input(_) => {
+                bail!("{url} asks for input, which --dump can't provide: {}", response.header.meta)
+            }
+            status => bail!("{status}: {}", response.header.meta),
+        }
+    }
+}
+
+// Gemtext as plain text, parsed the same way the browser does it
+fn render_gemtext(mut parser: Parser, source: &str) -> String {
+    let mut text = String::new();
+    for line in source.lines() {
+        match parser.parse(line) {
+            Line::Text(line)
+            | Line::Heading(line)
+            | Line::Subheading(line)
+            | Line::Subsubheading(line)
+            | Line::Preformatted(_, line) => text += &line,
+            Line::Link(url, label) | Line::Prompt(url, label) => {
+                if label.is_empty() {
+                    text += &url;
+                } else {
+                    text += &format!("{label} <{url}>");
+                }
+            }
+            Line::ListItem(item) => text += &format!("• {item}"),
+            Line::Quote(line) => text += &format!("> {line}"),
+            Line::PreformattedStart(_) | Line::PreformattedEnd => continue,
+        }
+        text += "\n";
+    }
+    text
+}
@@ -0,0 +1,471 @@
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_rustls::TlsConnector;
+use async_std::io::{Read, ReadExt, Write, WriteExt};
+use async_std::net::{TcpStream, ToSocketAddrs};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rustls::{Certificate, ClientConfig, ServerName};
+use thiserror::Error;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::cancel::Cancel;
+use crate::finger;
+use crate::gopher;
+use crate::header::{Header, StatusCode};
+use crate::identity::IdentityStore;
+use crate::spartan;
+use crate::tofu::{self, KnownHosts, Mismatch, TofuVerifier};
+
+pub const DEFAULT_PORT: u16 = 1965;
+// the spec limits the meta string to 1024 bytes, plus status, space and CRLF
+const MAX_HEADER_LEN: usize = 1029;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("Url has no host")]
+    MissingHost,
+    #[error("Invalid server name: {0}")]
+    InvalidServerName(String),
+    #[error("Connection error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Identity {0} can't be used: {1}")]
+    InvalidIdentity(String, rustls::Error),
+    #[error("Certificate for {} has changed", .0.host)]
+    CertificateChanged(Mismatch),
+    #[error("Malformed response header")]
+    MalformedHeader,
+    #[error("Timed out after {} seconds", .0.as_secs())]
+    Timeout(Duration),
+    #[error("Castor doesn't support {0} urls")]
+    UnsupportedScheme(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub header: Header,
+    pub body: Option<Vec<u8>>,
+    // the certificate the server presented, None for pages castor made itself
+    pub certificate: Option<CertificateInfo>,
+}
+
+// The parts of a server certificate worth showing to the user. Capsules
+// mostly use self signed certificates, so the names and dates are only
+// informative, the fingerprint is what TOFU checks.
+#[derive(Debug, Clone)]
+pub struct CertificateInfo {
+    pub fingerprint: String,
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+}
+
+impl CertificateInfo {
+    fn new(certificate: &Certificate) -> CertificateInfo {
+        let fingerprint = tofu::fingerprint(certificate);
+        match X509Certificate::from_der(&certificate.0) {
+            Ok((_, parsed)) => CertificateInfo {
+                fingerprint,
+                subject: parsed.subject().to_string(),
+                issuer: parsed.issuer().to_string(),
+                not_before: parsed.validity().not_before.to_string(),
+                not_after: parsed.validity().not_after.to_string(),
+            },
+            Err(_) => CertificateInfo {
+                fingerprint,
+                subject: String::from("unknown"),
+                issuer: String::from("unknown"),
+                not_before: String::from("unknown"),
+                not_after: String::from("unknown"),
+            },
+        }
+    }
+}
+
+// How far along a request is, reported as it happens
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Resolving,
+    Connecting,
+    Handshake,
+    // bytes of the request sent so far and how many there are, only
+    // reported for big ones like uploads
+    Sending(usize, usize),
+    WaitingForHeader,
+    // bytes received so far, header included
+    Receiving(usize),
+    // the header and the size of the body
+    Finished(Header, usize),
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Progress::Resolving => write!(f, "Looking up host…"),
+            Progress::Connecting => write!(f, "Connecting…"),
+            Progress::Handshake => write!(f, "Performing TLS handshake…"),
+            Progress::Sending(sent, total) => write!(f, "Sending… {sent} of {total} bytes"),
+            Progress::WaitingForHeader => write!(f, "Waiting for response…"),
+            Progress::Receiving(bytes) => write!(f, "Receiving… {bytes} bytes"),
+            Progress::Finished(header, bytes) => write!(f, "{header} · {bytes} bytes"),
+        }
+    }
+}
+
+// Something to send to a titan:// url
+#[derive(Debug, Clone)]
+pub struct Upload {
+    pub mime: String,
+    // lets servers check the upload is allowed, if they want one
+    pub token: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Upload {
+    // url with the titan parameters added to the end of its path
+    pub fn request_url(&self, url: &str) -> Result<String, Error> {
+        let mut url = url::Url::parse(url)?;
+        url.set_fragment(None);
+        let mut path = format!("{};mime={};size={}", url.path(), self.mime, self.data.len());
+        if let Some(token) = self.token.as_deref().filter(|token| !token.is_empty()) {
+            path += ";token=";
+            path += &utf8_percent_encode(token, NON_ALPHANUMERIC).to_string();
+        }
+        url.set_path(&path);
+        Ok(url.to_string())
+    }
+}
+
+pub struct Client {
+    known_hosts: Arc<Mutex<KnownHosts>>,
+    identities: RefCell<IdentityStore>,
+    // how long each step of a request may wait on the server, up to the
+    // header. Bodies take as long as they take, they're shown as they arrive.
+    timeout: Cell<Duration>,
+}
+
+impl Client {
+    pub fn new() -> anyhow::Result<Client> {
+        Ok(Client {
+            known_hosts: Arc::new(Mutex::new(KnownHosts::load()?)),
+            identities: RefCell::new(IdentityStore::load()?),
+            timeout: Cell::new(DEFAULT_TIMEOUT),
+        })
+    }
+
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.timeout.set(timeout);
+    }
+
+    pub fn identities(&self) -> Ref<IdentityStore> {
+        self.identities.borrow()
+    }
+
+    pub fn identities_mut(&self) -> RefMut<IdentityStore> {
+        self.identities.borrow_mut()
+    }
+
+    pub fn known_hosts(&self) -> Arc<Mutex<KnownHosts>> {
+        self.known_hosts.clone()
+    }
+
+    pub async fn async_request(&self, url: String) -> Result<Response, Error> {
+        let body = RefCell::new(Vec::new());
+        let mut response = self
+            .request_with_progress(url, &|_| {}, &|_, chunk| {
+                body.borrow_mut().extend_from_slice(chunk)
+            })
+            .await?;
+        let body = body.into_inner();
+        response.body = if body.is_empty() { None } else { Some(body) };
+        Ok(response)
+    }
+
+    // Just the header url responds with. The connection is dropped as soon as
+    // it's in, however much body might follow.
+    pub async fn header(&self, url: String) -> Result<Header, Error> {
+        let header = RefCell::new(None);
+        let cancel = Cancel::new();
+        let request = self.request_with_progress(url, &|_| {}, &|head, _| {
+            header.replace(Some(head.clone()));
+            cancel.cancel();
+        });
+        match cancel.run(request).await {
+            Some(response) => Ok(response?.header),
+            None => header.into_inner().ok_or(Error::MalformedHeader),
+        }
+    }
+
+    // Like async_request, but progress hears how the request is getting on
+    // and body is given the body a chunk at a time as it arrives, along with
+    // the header. The first chunk comes with the header and may be empty. The
+    // response doesn't keep the body, body is the only place it goes.
+    // Takes &self so several tabs can have requests in flight at once, nothing
+    // borrowed from the client is held across an await.
+    pub async fn request_with_progress(
+        &self,
+        url: String,
+        progress: &dyn Fn(Progress),
+        body: &dyn Fn(&Header, &[u8]),
+    ) -> Result<Response, Error> {
+        let parsed = url::Url::parse(&url)?;
+        let timeout = self.timeout.get();
+        match parsed.scheme() {
+            "finger" => finger::request(&parsed, timeout, progress, body).await,
+            "gopher" => gopher::request(&parsed, timeout, progress, body).await,
+            "spartan" => spartan::request(&parsed, timeout, progress, body).await,
+            "gemini" => {
+                let request = format!("{url}\r\n").into_bytes();
+                self.exchange(&url, &url, &request, progress, body).await
+            }
+            scheme => Err(Error::UnsupportedScheme(scheme.to_string())),
+        }
+    }
+
+    // Sends upload to a titan:// url. The server answers like a gemini
+    // server would, often with a redirect to the page that was changed.
+    pub async fn upload(
+        &self,
+        url: String,
+        upload: &Upload,
+        progress: &dyn Fn(Progress),
+        body: &dyn Fn(&Header, &[u8]),
+    ) -> Result<Response, Error> {
+        let mut request = format!("{}\r\n", upload.request_url(&url)?).into_bytes();
+        request.extend_from_slice(&upload.data);
+        // titan lives next to a gemini capsule on the same host and port, so
+        // it uses the identity picked for the gemini side of things
+        let identity_url = match url.strip_prefix("titan://") {
+            Some(rest) => format!("gemini://{rest}"),
+            None => url.clone(),
+        };
+        self.exchange(&url, &identity_url, &request, progress, body).await
+    }
+
+    // Connects to the host of url, sends request and reads the response
+    async fn exchange(
+        &self,
+        url: &str,
+        identity_url: &str,
+        request: &[u8],
+        progress: &dyn Fn(Progress),
+        body: &dyn Fn(&Header, &[u8]),
+    ) -> Result<Response, Error> {
+        let parsed = url::Url::parse(url)?;
+        let host = parsed.host_str().ok_or(Error::MissingHost)?.to_string();
+        let port = parsed.port().unwrap_or(DEFAULT_PORT);
+        let host_key = format!("{host}:{port}");
+
+        let mismatch = Arc::new(Mutex::new(None));
+        let verifier = TofuVerifier::new(self.known_hosts.clone(), host_key, mismatch.clone());
+        let config_builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier));
+        let config = match self.identities.borrow().for_url(identity_url) {
+            Some(identity) => config_builder
+                .with_single_cert(identity.certificates.clone(), identity.key.clone())
+                .map_err(|err| Error::InvalidIdentity(identity.name.clone(), err))?,
+            None => config_builder.with_no_client_auth(),
+        };
+        let server_name = ServerName::try_from(host.as_str())
+            .map_err(|_| Error::InvalidServerName(host.clone()))?;
+
+        let timeout = self.timeout.get();
+        let stream = connect(&host, port, timeout, progress).await?;
+        progress(Progress::Handshake);
+        let handshake = TlsConnector::from(Arc::new(config)).connect(server_name, stream);
+        let mut stream = match within(timeout, handshake).await? {
+            Ok(stream) => stream,
+            Err(err) => {
+                return Err(match mismatch.lock().unwrap().take() {
+                    Some(mismatch) => Error::CertificateChanged(mismatch),
+                    None => Error::Io(err),
+                })
+            }
+        };
+
+        let certificate = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .map(CertificateInfo::new);
+
+        send(&mut stream, request, timeout, progress).await?;
+        let (mut response, body_len) =
+            receive(&mut stream, &parse_header, timeout, progress, body).await?;
+        response.certificate = certificate;
+        progress(Progress::Finished(response.header.clone(), body_len));
+        Ok(response)
+    }
+}
+
+// Sends request to host without TLS, for the protocols that don't use it.
+// parse finds the header in what comes back and where the body starts.
+pub async fn plain_exchange(
+    host: &str,
+    port: u16,
+    request: &[u8],
+    parse: &dyn Fn(&[u8]) -> Result<Option<(Header, usize)>, Error>,
+    timeout: Duration,
+    progress: &dyn Fn(Progress),
+    body: &dyn Fn(&Header, &[u8]),
+) -> Result<Response, Error> {
+    let mut stream = connect(host, port, timeout, progress).await?;
+    send(&mut stream, request, timeout, progress).await?;
+    let (response, body_len) = receive(&mut stream, parse, timeout, progress, body).await?;
+    progress(Progress::Finished(response.header.clone(), body_len));
+    Ok(response)
+}
+
+// Runs future, unless it takes longer than timeout
+async fn within<F: Future>(timeout: Duration, future: F) -> Result<F::Output, Error> {
+    async_std::future::timeout(timeout, future)
+        .await
+        .map_err(|_| Error::Timeout(timeout))
+}
+
+async fn connect(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    progress: &dyn Fn(Progress),
+) -> Result<TcpStream, Error> {
+    progress(Progress::Resolving);
+    let addresses: Vec<_> = within(timeout, (host, port).to_socket_addrs()).await??.collect();
+    progress(Progress::Connecting);
+    Ok(within(timeout, TcpStream::connect(&addresses[..])).await??)
+}
+
+// The timeout is for each part rather than the whole request, so big uploads
+// have as long as they need as long as they keep going
+async fn send<S: Write + Unpin>(
+    stream: &mut S,
+    request: &[u8],
+    timeout: Duration,
+    progress: &dyn Fn(Progress),
+) -> Result<(), Error> {
+    // sent a bit at a time so big uploads can show how far along they are
+    for (index, part) in request.chunks(16 * 1024).enumerate() {
+        within(timeout, stream.write_all(part)).await??;
+        if request.len() > 16 * 1024 {
+            let sent = (index * 16 * 1024 + part.len()).min(request.len());
+            progress(Progress::Sending(sent, request.len()));
+        }
+    }
+    within(timeout, stream.flush()).await??;
+    progress(Progress::WaitingForHeader);
+    Ok(())
+}
+
+// Reads until the server closes the connection, handing the body to body as
+// it arrives once parse has found the header. The response doesn't keep the
+// body, so big ones needn't all be held in memory, and the size of it comes
+// back alongside. Only the wait for the header can time out, pages that
+// trickle in are shown as they do.
+async fn receive<S: Read + Unpin>(
+    stream: &mut S,
+    parse: &dyn Fn(&[u8]) -> Result<Option<(Header, usize)>, Error>,
+    timeout: Duration,
+    progress: &dyn Fn(Progress),
+    body: &dyn Fn(&Header, &[u8]),
+) -> Result<(Response, usize), Error> {
+    // what's come back before the end of the header
+    let mut data = Vec::new();
+    let mut chunk = [0; 16 * 1024];
+    let mut header = None;
+    let mut received = 0;
+    let mut body_len = 0;
+    loop {
+        let read = match header {
+            Some(_) => stream.read(&mut chunk).await,
+            None => within(timeout, stream.read(&mut chunk)).await?,
+        };
+        match read {
+            Ok(0) => break,
+            Ok(read) => {
+                received += read;
+                progress(Progress::Receiving(received));
+                match &header {
+                    Some(header) => {
+                        body(header, &chunk[..read]);
+                        body_len += read;
+                    }
+                    None => {
+                        data.extend_from_slice(&chunk[..read]);
+                        if let Some((parsed, body_start)) = parse(&data)? {
+                            // even with no body yet, so body hears about
+                            // the header as soon as it's in
+                            body(&parsed, &data[body_start..]);
+                            body_len += data.len() - body_start;
+                            header = Some(parsed);
+                            data = Vec::new();
+                        }
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            // plenty of servers close the connection without a close_notify
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && received > 0 => break,
+            Err(err) => return Err(Error::Io(err)),
+        }
+    }
+
+    // a header can still be found in nothing at all, for the protocols that
+    // don't have one
+    let header = match header {
+        Some(header) => header,
+        None => parse(&data)?.ok_or(Error::MalformedHeader)?.0,
+    };
+    let response = Response {
+        header,
+        body: None,
+        certificate: None,
+    };
+    Ok((response, body_len))
+}
+
+fn parse_header(data: &[u8]) -> Result<Option<(Header, usize)>, Error> {
+    parse_header_with(data, |status| {
+        status
+            .parse::<u8>()
+            .ok()
+            .filter(|_| status.len() == 2)
+            .and_then(StatusCode::from_code)
+    })
+}
+
+// The header and where the body starts, or None if the header hasn't all
+// arrived yet. status turns the status in the header into one of ours, for
+// protocols whose headers look like gemini's but use other codes.
+pub fn parse_header_with(
+    data: &[u8],
+    status: fn(&str) -> Option<StatusCode>,
+) -> Result<Option<(Header, usize)>, Error> {
+    let header_end = match data
+        .windows(2)
+        .take(MAX_HEADER_LEN)
+        .position(|window| window == b"\r\n")
+    {
+        Some(header_end) => header_end,
+        None if data.len() < MAX_HEADER_LEN => return Ok(None),
+        None => return Err(Error::MalformedHeader),
+    };
+    let header = std::str::from_utf8(&data[..header_end]).map_err(|_| Error::MalformedHeader)?;
+
+    let (code, meta) = header.split_once(' ').unwrap_or((header, ""));
+    let status = status(code).ok_or(Error::MalformedHeader)?;
+    let header = Header {
+        status,
+        meta: meta.trim().to_string(),
+    };
+    Ok(Some((header, header_end + 2)))
+}
@@ -0,0 +1,223 @@
+use std::cell::{Cell, RefCell};
+
+use gtk::{prelude::*, Builder, Button, CheckButton, Label, SearchBar, SearchEntry, TextView};
+use gtk4 as gtk;
+
+// The find in page bar. Every match in the current page gets the "find-match"
+// tag, the one stepped to also gets "find-current".
+pub struct FindBar {
+    pub bar: SearchBar,
+    pub entry: SearchEntry,
+    pub previous: Button,
+    pub next: Button,
+    pub match_case: CheckButton,
+    pub whole_word: CheckButton,
+    count: Label,
+    // character offsets of the matches in the buffer searched last
+    matches: RefCell<Vec<(i32, i32)>>,
+    current: Cell<usize>,
+}
+
+impl FindBar {
+    pub fn new(builder: &Builder) -> FindBar {
+        let bar: SearchBar = builder.object("find_bar").expect("Couldn't get find bar");
+        let entry: SearchEntry = builder.object("find_entry").expect("Couldn't get find entry");
+        bar.connect_entry(&entry);
+        FindBar {
+            bar,
+            entry,
+            previous: builder.object("find_previous").expect("Couldn't get find previous button"),
+            next: builder.object("find_next").expect("Couldn't get find next button"),
+            match_case: builder.object("find_match_case").expect("Couldn't get match case option"),
+            whole_word: builder.object("find_whole_word").expect("Couldn't get whole word option"),
+            count: builder.object("find_count").expect("Couldn't get find count"),
+            matches: RefCell::new(Vec::new()),
+            current: Cell::new(0),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.bar.is_search_mode()
+    }
+
+    pub fn open(&self) {
+        self.bar.set_search_mode(true);
+        self.entry.grab_focus();
+        self.entry.select_region(0, -1);
+    }
+
+    // Highlights every match in text_view and moves to the first one
+    pub fn search(&self, text_view: &TextView) {
+        self.clear(text_view);
+        let needle: Vec<char> = self.entry.text().chars().collect();
+        if needle.is_empty() {
+            self.count.set_text("");
+            return;
+        }
+
+        // the slice has a placeholder for images and other embedded widgets,
+        // so offsets into it line up with the buffer's
+        let buffer = text_view.buffer();
+        let text: Vec<char> = buffer
+            .slice(&buffer.start_iter(), &buffer.end_iter(), true)
+            .chars()
+            .collect();
+        let matches = find_matches(
+            &text,
+            &needle,
+            self.match_case.is_active(),
+            self.whole_word.is_active(),
+        );
+
+        if let Some(tag) = buffer.tag_table().lookup("find-match") {
+            for (start, end) in &matches {
+                buffer.apply_tag(
+                    &tag,
+                    &buffer.iter_at_offset(*start as i32),
+                    &buffer.iter_at_offset(*end as i32),
+                );
+            }
+        }
+        self.matches.replace(
+            matches
+                .into_iter()
+                .map(|(start, end)| (start as i32, end as i32))
+                .collect(),
+        );
+        self.current.set(0);
+        self.show_current(text_view);
+    }
+
+    // Moves to the next match, or the previous one, wrapping around the ends
+    pub fn step(&self, text_view: &TextView, forward: bool) {
+        let len = self.matches.borrow().len();
+        if len == 0 {
+            return;
+        }
+        let current = self.current.get();
+        self.current.set(if forward {
+            (current + 1) % len
+        } else {
+            (current + len - 1) % len
+        });
+        self.show_current(text_view);
+    }
+
+    // Removes the highlighting from text_view
+    pub fn clear(&self, text_view: &TextView) {
+        let buffer = text_view.buffer();
+        for name in ["find-match", "find-current"] {
+            if let Some(tag) = buffer.tag_table().lookup(name) {
+                buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+            }
+        }
+        self.matches.borrow_mut().clear();
+    }
+
+    fn show_current(&self, text_view: &TextView) {
+        let matches = self.matches.borrow();
+        let (start, end) = match matches.get(self.current.get()) {
+            Some(current) => *current,
+            None => {
+                self.count.set_text("No matches");
+                return;
+            }
+        };
+        self.count
+            .set_text(&format!("{} of {}", self.current.get() + 1, matches.len()));
+
+        let buffer = text_view.buffer();
+        if let Some(tag) = buffer.tag_table().lookup("find-current") {
+            buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+            buffer.apply_tag(&tag, &buffer.iter_at_offset(start), &buffer.iter_at_offset(end));
+        }
+        // scrolling to a mark works even before the lines have been laid out
+        let iter = buffer.iter_at_offset(start);
+        let mark = match buffer.mark("find-current") {
+            Some(mark) => {
+                buffer.move_mark(&mark, &iter);
+                mark
+            }
+            None => buffer.create_mark(Some("find-current"), &iter, true),
+        };
+        text_view.scroll_to_mark(&mark, 0.1, false, 0.0, 0.0);
+    }
+}
+
+// Start and end offsets of every occurrence of needle in text, whole words
+// have no letter, digit or underscore right before or after them
+fn find_matches(
+    text: &[char],
+    needle: &[char],
+    match_case: bool,
+    whole_word: bool,
+) -> Vec<(usize, usize)> {
+    let same = |a: char, b: char| {
+        if match_case {
+            a == b
+        } else {
+            a == b || a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+
+    let mut matches = Vec::new();
+    if needle.is_empty() || needle.len() > text.len() {
+        return matches;
+    }
+    let mut start = 0;
+    while start + needle.len() <= text.len() {
+        let end = start + needle.len();
+        let found = text[start..end]
+            .iter()
+            .zip(needle)
+            .all(|(a, b)| same(*a, *b));
+        let bounded = !whole_word
+            || (start.checked_sub(1).and_then(|i| text.get(i)).map_or(true, |c| !is_word(c))
+                && text.get(end).map_or(true, |c| !is_word(c)));
+        if found && bounded {
+            matches.push((start, end));
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str, needle: &str, match_case: bool, whole_word: bool) -> Vec<(usize, usize)> {
+        let text: Vec<char> = text.chars().collect();
+        let needle: Vec<char> = needle.chars().collect();
+        find_matches(&text, &needle, match_case, whole_word)
+    }
+
+    #[test]
+    fn ignores_case_unless_asked() {
+        assert_eq!(find("Gemini gemini", "gemini", false, false), vec![(0, 6), (7, 13)]);
+        assert_eq!(find("Gemini gemini", "gemini", true, false), vec![(7, 13)]);
+    }
+
+    #[test]
+    fn whole_words_need_boundaries() {
+        assert_eq!(find("cat concat cat_ cat.", "cat", false, true), vec![(0, 3), (16, 19)]);
+    }
+
+    #[test]
+    fn offsets_count_characters() {
+        assert_eq!(find("héllo wörld", "wörld", false, false), vec![(6, 11)]);
+    }
+
+    #[test]
+    fn matches_do_not_overlap() {
+        assert_eq!(find("aaaa", "aa", false, false), vec![(0, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn empty_needle_finds_nothing() {
+        assert!(find("text", "", false, false).is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use percent_encoding::percent_decode_str;
+
+use crate::client::{self, Error, Progress, Response};
+use crate::header::{Header, StatusCode};
+
+const DEFAULT_PORT: u16 = 79;
+
+// Asks a finger server about a user, named either before the host as in
+// finger://user@host or in the path as in finger://host/user. The answer is
+// plain text, made to look like a gemini response.
+pub async fn request(
+    url: &url::Url,
+    timeout: Duration,
+    progress: &dyn Fn(Progress),
+    body: &dyn Fn(&Header, &[u8]),
+) -> Result<Response, Error> {
+    let host = url.host_str().ok_or(Error::MissingHost)?;
+    let header = Header {
+        status: StatusCode::Success,
+        meta: String::from("text/plain"),
+    };
+    let request = format!("{}\r\n", query(url));
+    client::plain_exchange(
+        host,
+        url.port().unwrap_or(DEFAULT_PORT),
+        request.as_bytes(),
+        // there's no header, everything is body
+        &|_| Ok(Some((header.clone(), 0))),
+        timeout,
+        progress,
+        body,
+    )
+    .await
+}
+
+// The user to ask about, servers list who's around when it's empty
+fn query(url: &url::Url) -> String {
+    let user = if url.username().is_empty() {
+        url.path().trim_start_matches('/')
+    } else {
+        url.username()
+    };
+    percent_decode_str(user).decode_utf8_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_comes_from_either_place() {
+        let query = |url| super::query(&url::Url::parse(url).unwrap());
+        assert_eq!(query("finger://alice@example.org"), "alice");
+        assert_eq!(query("finger://example.org/bob"), "bob");
+        assert_eq!(query("finger://example.org"), "");
+    }
+}
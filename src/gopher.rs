@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::client::{self, Error, Progress, Response};
+use crate::header::{Header, InputCode, StatusCode};
+
+const DEFAULT_PORT: u16 = 70;
+
+// The mime type gopher menus are shown with, they get turned into a list of
+// links as they're rendered
+pub const MENU_MIME: &str = "text/x-gopher-menu";
+
+// characters that can't go in the selector part of a url as they are
+const SELECTOR: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+// Fetches a gopher item. The response is made to look like a gemini one, the
+// item type in the url deciding its mime type. Search servers (type 7) answer
+// with an input header until the url has a query to search for.
+pub async fn request(
+    url: &url::Url,
+    timeout: Duration,
+    progress: &dyn Fn(Progress),
+    body: &dyn Fn(&Header, &[u8]),
+) -> Result<Response, Error> {
+    let host = url.host_str().ok_or(Error::MissingHost)?;
+    let (item_type, mut selector) = item(url);
+    let search = url
+        .query()
+        .map(|query| percent_decode_str(query).decode_utf8_lossy().to_string());
+    match search {
+        Some(search) => {
+            selector.push('\t');
+            selector += &search;
+        }
+        None if item_type == '7' && !selector.contains('\t') => {
+            return Ok(Response {
+                header: Header {
+                    status: StatusCode::Input(InputCode::Input),
+                    meta: String::from("Search"),
+                },
+                body: None,
+                certificate: None,
+            });
+        }
+        None => {}
+    }
+
+    let header = Header {
+        status: StatusCode::Success,
+        meta: mime(item_type).to_string(),
+    };
+    let request = format!("{selector}\r\n");
+    client::plain_exchange(
+        host,
+        url.port().unwrap_or(DEFAULT_PORT),
+        request.as_bytes(),
+        // there's no header, everything is body
+        &|_| Ok(Some((header.clone(), 0))),
+        timeout,
+        progress,
+        body,
+    )
+    .await
+}
+
+// The item type and selector of a gopher url, urls with no type are menus
+fn item(url: &url::Url) -> (char, String) {
+    let path = percent_decode_str(url.path()).decode_utf8_lossy();
+    let mut chars = path.trim_start_matches('/').chars();
+    match chars.next() {
+        Some(item_type) => (item_type, chars.as_str().to_string()),
+        None => ('1', String::new()),
+    }
+}
+
+fn mime(item_type: char) -> &'static str {
+    match item_type {
+        '0' => "text/plain",
+        '1' | '7' => MENU_MIME,
+        'h' => "text/html",
+        'g' => "image/gif",
+        'p' => "image/png",
+        // gdk works out what kind of image it is from the data
+        'I' | ':' => "image/x-gopher",
+        'd' => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+// A line of a gopher menu
+#[derive(Debug, PartialEq, Eq)]
+pub struct MenuItem {
+    pub text: String,
+    // where it leads, None for informational lines
+    pub url: Option<String>,
+}
+
+// Parses a line of a gopher menu, None for the line that marks the end
+pub fn menu_item(line: &str) -> Option<MenuItem> {
+    if line == "." {
+        return None;
+    }
+    let mut chars = line.chars();
+    let item_type = match chars.next() {
+        Some(item_type) => item_type,
+        None => {
+            return Some(MenuItem {
+                text: String::new(),
+                url: None,
+            })
+        }
+    };
+    let mut fields = chars.as_str().split('\t');
+    let text = fields.next().unwrap_or_default().to_string();
+    let selector = fields.next().unwrap_or_default();
+    let host = fields.next().unwrap_or_default();
+    let port = match fields.next().map(str::trim) {
+        Some("") | Some("70") | None => String::new(),
+        Some(port) => format!(":{port}"),
+    };
+
+    let url = match item_type {
+        'i' | '3' => None,
+        // links out of gopherspace
+        'h' if selector.starts_with("URL:") => Some(selector["URL:".len()..].to_string()),
+        '8' | 'T' => Some(format!("telnet://{host}{port}")),
+        _ if host.is_empty() => None,
+        _ => Some(format!(
+            "gopher://{host}{port}/{item_type}{}",
+            utf8_percent_encode(selector, SELECTOR)
+        )),
+    };
+    Some(MenuItem { text, url })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_name_type_and_selector() {
+        let url = url::Url::parse("gopher://example.org/0/docs/read%20me.txt").unwrap();
+        assert_eq!(item(&url), ('0', String::from("/docs/read me.txt")));
+        let url = url::Url::parse("gopher://example.org").unwrap();
+        assert_eq!(item(&url), ('1', String::new()));
+    }
+
+    #[test]
+    fn menu_lines_become_links() {
+        assert_eq!(
+            menu_item("1Phlog\t/phlog\texample.org\t70"),
+            Some(MenuItem {
+                text: String::from("Phlog"),
+                url: Some(String::from("gopher://example.org/1/phlog")),
+            })
+        );
+        assert_eq!(
+            menu_item("0Read me\t/read me.txt\texample.org\t7070").unwrap().url,
+            Some(String::from("gopher://example.org:7070/0/read%20me.txt"))
+        );
+        assert_eq!(
+            menu_item("hWeb\tURL:https://example.org/\texample.org\t70").unwrap().url,
+            Some(String::from("https://example.org/"))
+        );
+        assert_eq!(menu_item("iWelcome\t\terror.host\t1").unwrap().url, None);
+        assert_eq!(menu_item("."), None);
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+
+// Response header of a gemini request, "<STATUS><SPACE><META><CR><LF>"
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub status: StatusCode,
+    pub meta: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusCode {
+    Input(InputCode),
+    Success,
+    Redirect(RedirectCode),
+    FailTemporary(FailTemporaryCode),
+    FailPermanent(FailPermanentCode),
+    CertFail(CertFailCode),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputCode {
+    Input,
+    Sensitive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectCode {
+    Temporary,
+    Permanent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailTemporaryCode {
+    TemporaryFailure,
+    ServerUnavailable,
+    CgiError,
+    ProxyError,
+    SlowDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailPermanentCode {
+    PermanentFailure,
+    NotFound,
+    Gone,
+    ProxyRequestRefused,
+    BadRequest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertFailCode {
+    CertificateRequired,
+    CertificateNotAuthorized,
+    CertificateNotValid,
+}
+
+impl StatusCode {
+    // Clients are told to fall back on the first digit for codes they don't
+    // know about, so only that digit decides whether a code is valid.
+    pub fn from_code(code: u8) -> Option<StatusCode> {
+        let status = match code {
+            10..=19 => StatusCode::Input(match code {
+                11 => InputCode::Sensitive,
+                _ => InputCode::Input,
+            }),
+            20..=29 => StatusCode::Success,
+            30..=39 => StatusCode::Redirect(match code {
+                31 => RedirectCode::Permanent,
+                _ => RedirectCode::Temporary,
+            }),
+            40..=49 => StatusCode::FailTemporary(match code {
+                41 => FailTemporaryCode::ServerUnavailable,
+                42 => FailTemporaryCode::CgiError,
+                43 => FailTemporaryCode::ProxyError,
+                44 => FailTemporaryCode::SlowDown,
+                _ => FailTemporaryCode::TemporaryFailure,
+            }),
+            50..=59 => StatusCode::FailPermanent(match code {
+                51 => FailPermanentCode::NotFound,
+                52 => FailPermanentCode::Gone,
+                53 => FailPermanentCode::ProxyRequestRefused,
+                59 => FailPermanentCode::BadRequest,
+                _ => FailPermanentCode::PermanentFailure,
+            }),
+            60..=69 => StatusCode::CertFail(match code {
+                61 => CertFailCode::CertificateNotAuthorized,
+                62 => CertFailCode::CertificateNotValid,
+                _ => CertFailCode::CertificateRequired,
+            }),
+            _ => return None,
+        };
+        Some(status)
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            StatusCode::Input(InputCode::Input) => 10,
+            StatusCode::Input(InputCode::Sensitive) => 11,
+            StatusCode::Success => 20,
+            StatusCode::Redirect(RedirectCode::Temporary) => 30,
+            StatusCode::Redirect(RedirectCode::Permanent) => 31,
+            StatusCode::FailTemporary(code) => code.code(),
+            StatusCode::FailPermanent(code) => code.code(),
+            StatusCode::CertFail(code) => code.code(),
+        }
+    }
+}
+
+impl FailTemporaryCode {
+    pub fn code(&self) -> u8 {
+        match self {
+            FailTemporaryCode::TemporaryFailure => 40,
+            FailTemporaryCode::ServerUnavailable => 41,
+            FailTemporaryCode::CgiError => 42,
+            FailTemporaryCode::ProxyError => 43,
+            FailTemporaryCode::SlowDown => 44,
+        }
+    }
+}
+
+impl FailPermanentCode {
+    pub fn code(&self) -> u8 {
+        match self {
+            FailPermanentCode::PermanentFailure => 50,
+            FailPermanentCode::NotFound => 51,
+            FailPermanentCode::Gone => 52,
+            FailPermanentCode::ProxyRequestRefused => 53,
+            FailPermanentCode::BadRequest => 59,
+        }
+    }
+}
+
+impl CertFailCode {
+    pub fn code(&self) -> u8 {
+        match self {
+            CertFailCode::CertificateRequired => 60,
+            CertFailCode::CertificateNotAuthorized => 61,
+            CertFailCode::CertificateNotValid => 62,
+        }
+    }
+}
+
+// Formats the header the way it was sent, minus the line ending
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status.code(), self.meta)
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusCode::Input(InputCode::Input) => write!(f, "10 Input"),
+            StatusCode::Input(InputCode::Sensitive) => write!(f, "11 Sensitive input"),
+            StatusCode::Success => write!(f, "20 Success"),
+            StatusCode::Redirect(RedirectCode::Temporary) => write!(f, "30 Temporary redirect"),
+            StatusCode::Redirect(RedirectCode::Permanent) => write!(f, "31 Permanent redirect"),
+            StatusCode::FailTemporary(code) => write!(f, "{code}"),
+            StatusCode::FailPermanent(code) => write!(f, "{code}"),
+            StatusCode::CertFail(code) => write!(f, "{code}"),
+        }
+    }
+}
+
+impl fmt::Display for FailTemporaryCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            FailTemporaryCode::TemporaryFailure => "Temporary failure",
+            FailTemporaryCode::ServerUnavailable => "Server unavailable",
+            FailTemporaryCode::CgiError => "CGI error",
+            FailTemporaryCode::ProxyError => "Proxy error",
+            FailTemporaryCode::SlowDown => "Slow down",
+        };
+        write!(f, "{} {description}", self.code())
+    }
+}
+
+impl fmt::Display for FailPermanentCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            FailPermanentCode::PermanentFailure => "Permanent failure",
+            FailPermanentCode::NotFound => "Not found",
+            FailPermanentCode::Gone => "Gone",
+            FailPermanentCode::ProxyRequestRefused => "Proxy request refused",
+            FailPermanentCode::BadRequest => "Bad request",
+        };
+        write!(f, "{} {description}", self.code())
+    }
+}
+
+impl fmt::Display for CertFailCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            CertFailCode::CertificateRequired => "Client certificate required",
+            CertFailCode::CertificateNotAuthorized => "Certificate not authorized",
+            CertFailCode::CertificateNotValid => "Certificate not valid",
+        };
+        write!(f, "{} {description}", self.code())
+    }
+}
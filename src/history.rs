@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Visit {
+    pub url: String,
+    pub title: String,
+    // seconds since the unix epoch
+    pub time: i64,
+}
+
+// Every page castor has loaded, kept in the castor data directory as
+// "<time>\t<url>\t<title>" lines in the order they were visited.
+pub struct History {
+    path: PathBuf,
+    visits: Vec<Visit>,
+    // the urls in visits, so pages can check every link they have quickly
+    visited: HashSet<String>,
+}
+
+impl History {
+    pub fn load() -> Result<History> {
+        let path = crate::data_dir().join("history");
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let visits: Vec<Visit> = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let time = fields.next()?.parse().ok()?;
+                let url = fields.next()?.to_string();
+                let title = fields.next().unwrap_or_default().to_string();
+                Some(Visit { url, title, time })
+            })
+            .collect();
+
+        let visited = visits.iter().map(|visit| visit.url.clone()).collect();
+        Ok(History {
+            path,
+            visits,
+            visited,
+        })
+    }
+
+    pub fn record(&mut self, url: &str, title: &str) -> Result<()> {
+        let visit = Visit {
+            url: url.to_string(),
+            title: title.replace(['\t', '\n', '\r'], " "),
+            time: now(),
+        };
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}\t{}\t{}", visit.time, visit.url, visit.title)
+            .context("Failed to record visit")?;
+
+        self.visited.insert(visit.url.clone());
+        self.visits.push(visit);
+        Ok(())
+    }
+
+    // Whether url has ever been visited
+    pub fn contains(&self, url: &str) -> bool {
+        self.visited.contains(url)
+    }
+
+    // Visits whose url or title contain term, ignoring case, newest first
+    pub fn search(&self, term: &str) -> Vec<&Visit> {
+        let term = term.to_lowercase();
+        self.visits
+            .iter()
+            .rev()
+            .filter(|visit| {
+                term.is_empty()
+                    || visit.url.to_lowercase().contains(&term)
+                    || visit.title.to_lowercase().contains(&term)
+            })
+            .collect()
+    }
+
+    // Forgets every visit made at or after since, pass 0 to clear everything
+    pub fn clear_since(&mut self, since: i64) -> Result<()> {
+        self.visits.retain(|visit| visit.time < since);
+        self.visited = self.visits.iter().map(|visit| visit.url.clone()).collect();
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut contents = String::new();
+        for visit in &self.visits {
+            contents += &format!("{}\t{}\t{}\n", visit.time, visit.url, visit.title);
+        }
+        fs::write(&self.path, contents)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,135 @@
+use std::rc::Rc;
+
+use glib::clone;
+use gtk::{
+    pango, prelude::*, Button, ComboBoxText, Label, ListBox, ListBoxRow, Orientation,
+    ScrolledWindow, SearchEntry, SelectionMode, Window,
+};
+use gtk4 as gtk;
+
+use crate::browser::Browser;
+use crate::history;
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+
+// Opens a window listing the browsing history grouped by day, with a search
+// entry to filter it and a way to clear part or all of it.
+pub fn show(browser: &Rc<Browser>) {
+    let window = Window::builder()
+        .title("History")
+        .transient_for(&browser.window)
+        .default_width(500)
+        .default_height(600)
+        .build();
+
+    let content = gtk::Box::new(Orientation::Vertical, 6);
+    content.set_margin_top(6);
+    content.set_margin_bottom(6);
+    content.set_margin_start(6);
+    content.set_margin_end(6);
+    window.set_child(Some(&content));
+
+    let search = SearchEntry::builder()
+        .placeholder_text("Search history")
+        .build();
+    content.append(&search);
+
+    let list = ListBox::builder()
+        .selection_mode(SelectionMode::None)
+        .activate_on_single_click(true)
+        .build();
+    content.append(
+        &ScrolledWindow::builder()
+            .vexpand(true)
+            .child(&list)
+            .build(),
+    );
+
+    let clear_box = gtk::Box::new(Orientation::Horizontal, 6);
+    let range = ComboBoxText::new();
+    range.append(Some("hour"), "Last hour");
+    range.append(Some("day"), "Last day");
+    range.append(Some("week"), "Last week");
+    range.append(Some("all"), "Everything");
+    range.set_active_id(Some("hour"));
+    let clear = Button::with_label("Clear history");
+    clear_box.append(&Label::new(Some("Time range:")));
+    clear_box.append(&range);
+    clear_box.append(&clear);
+    content.append(&clear_box);
+
+    let weak = Rc::downgrade(browser);
+    let populate = clone!(@strong weak, @weak list, @weak search => move || {
+        let browser = match weak.upgrade() {
+            Some(browser) => browser,
+            None => return,
+        };
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+
+        let history = browser.profile.history.borrow();
+        let mut current_day = None;
+        for visit in history.search(&search.text()) {
+            let day = glib::DateTime::from_unix_local(visit.time)
+                .and_then(|time| time.format("%A %e %B %Y"))
+                .map(|day| day.to_string())
+                .unwrap_or_default();
+            if current_day.as_ref() != Some(&day) {
+                let header = Label::builder()
+                    .label(&day)
+                    .xalign(0.0)
+                    .margin_top(12)
+                    .css_classes(vec![String::from("heading")])
+                    .build();
+                list.append(&ListBoxRow::builder().child(&header).activatable(false).build());
+                current_day = Some(day);
+            }
+
+            let time = glib::DateTime::from_unix_local(visit.time)
+                .and_then(|time| time.format("%H:%M"))
+                .map(|time| time.to_string())
+                .unwrap_or_default();
+            let title = if visit.title.is_empty() { &visit.url } else { &visit.title };
+            let label = Label::builder()
+                .label(&format!("{time}  {title}"))
+                .xalign(0.0)
+                .ellipsize(pango::EllipsizeMode::End)
+                .build();
+            list.append(&ListBoxRow::builder().child(&label).tooltip_text(&visit.url).build());
+        }
+    });
+    populate();
+
+    search.connect_search_changed(clone!(@strong populate => move |_| populate()));
+
+    list.connect_row_activated(clone!(@strong weak => move |_, row| {
+        let browser = match weak.upgrade() {
+            Some(browser) => browser,
+            None => return,
+        };
+        if let (Some(url), Some(tab)) = (row.tooltip_text(), browser.current_tab()) {
+            browser.navigate(&tab, url.to_string());
+        }
+    }));
+
+    clear.connect_clicked(clone!(@strong weak, @weak range, @strong populate => move |_| {
+        let browser = match weak.upgrade() {
+            Some(browser) => browser,
+            None => return,
+        };
+        let since = match range.active_id().as_deref() {
+            Some("hour") => history::now() - HOUR,
+            Some("day") => history::now() - DAY,
+            Some("week") => history::now() - 7 * DAY,
+            _ => 0,
+        };
+        if let Err(err) = browser.profile.history.borrow_mut().clear_since(since) {
+            eprintln!("Failed to clear history: {err:#}");
+        }
+        populate();
+    }));
+
+    window.present();
+}
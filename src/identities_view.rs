@@ -0,0 +1,129 @@
+use std::rc::Rc;
+
+use glib::{clone, MainContext};
+use gtk::{
+    pango, prelude::*, Button, ButtonsType, Label, ListBox, ListBoxRow, MessageDialog,
+    Orientation, ScrolledWindow, SelectionMode, Window,
+};
+use gtk4 as gtk;
+
+use crate::browser::Browser;
+
+// Opens a window listing the identities castor has and the urls each one is
+// used for, with ways to stop using one somewhere or to delete it altogether
+pub fn show(browser: &Rc<Browser>) {
+    let window = Window::builder()
+        .title("Identities")
+        .transient_for(&browser.window)
+        .default_width(500)
+        .default_height(400)
+        .build();
+
+    let list = ListBox::builder()
+        .selection_mode(SelectionMode::None)
+        .build();
+    window.set_child(Some(
+        &ScrolledWindow::builder()
+            .vexpand(true)
+            .child(&list)
+            .build(),
+    ));
+
+    let weak = Rc::downgrade(browser);
+    let identities = browser.profile.client.identities();
+    if identities.identities().is_empty() {
+        let label = Label::builder()
+            .label("No identities yet, capsules that want one will ask for it")
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+        list.append(&ListBoxRow::builder().child(&label).activatable(false).build());
+    }
+    for identity in identities.identities() {
+        let content = gtk::Box::new(Orientation::Vertical, 6);
+        content.set_margin_top(6);
+        content.set_margin_bottom(6);
+        content.set_margin_start(6);
+        content.set_margin_end(6);
+        let row = ListBoxRow::builder().child(&content).activatable(false).build();
+
+        let heading = gtk::Box::new(Orientation::Horizontal, 6);
+        heading.append(
+            &Label::builder()
+                .label(&identity.name)
+                .xalign(0.0)
+                .hexpand(true)
+                .css_classes(vec![String::from("heading")])
+                .build(),
+        );
+        let delete = Button::with_label("Delete");
+        heading.append(&delete);
+        content.append(&heading);
+
+        let scopes = identities.scopes(&identity.name);
+        if scopes.is_empty() {
+            content.append(&Label::builder().label("Not used anywhere").xalign(0.0).build());
+        }
+        for scope in scopes {
+            let scope_box = gtk::Box::new(Orientation::Horizontal, 6);
+            scope_box.append(
+                &Label::builder()
+                    .label(scope)
+                    .xalign(0.0)
+                    .hexpand(true)
+                    .ellipsize(pango::EllipsizeMode::End)
+                    .tooltip_text(scope)
+                    .build(),
+            );
+            let stop = Button::with_label("Stop using here");
+            scope_box.append(&stop);
+            content.append(&scope_box);
+
+            let scope = scope.to_string();
+            stop.connect_clicked(clone!(@strong weak, @weak content, @weak scope_box => move |_| {
+                let browser = match weak.upgrade() {
+                    Some(browser) => browser,
+                    None => return,
+                };
+                if let Err(err) = browser.profile.client.identities_mut().unbind(&scope) {
+                    eprintln!("Failed to stop using identity: {err:#}");
+                    return;
+                }
+                content.remove(&scope_box);
+            }));
+        }
+        list.append(&row);
+
+        let name = identity.name.clone();
+        delete.connect_clicked(clone!(@strong weak, @weak window, @weak list, @weak row => move |_| {
+            let name = name.clone();
+            MainContext::default().spawn_local(clone!(@strong weak, @weak window, @weak list, @weak row => async move {
+                // capsules know people by their identity, so there's no
+                // getting back in as the same person once it's gone
+                let dialog = MessageDialog::builder()
+                    .transient_for(&window)
+                    .modal(true)
+                    .buttons(ButtonsType::YesNo)
+                    .text(&format!("Delete the identity {name}?"))
+                    .secondary_text("Capsules that know you by it won't recognise you any more.")
+                    .build();
+                let response = dialog.run_future().await;
+                dialog.close();
+                if response != gtk::ResponseType::Yes {
+                    return;
+                }
+                let browser = match weak.upgrade() {
+                    Some(browser) => browser,
+                    None => return,
+                };
+                if let Err(err) = browser.profile.client.identities_mut().delete(&name) {
+                    eprintln!("Failed to delete identity: {err:#}");
+                    return;
+                }
+                list.remove(&row);
+            }));
+        }));
+    }
+
+    window.present();
+}
@@ -0,0 +1,314 @@
+use std::fs;
+use std::io::{BufReader, ErrorKind, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use rustls::{Certificate, PrivateKey};
+
+// A client certificate and its key, presented to capsules that ask for one
+#[derive(Clone)]
+pub struct Identity {
+    pub name: String,
+    pub certificates: Vec<Certificate>,
+    pub key: PrivateKey,
+}
+
+// Identities live in the identities directory of the castor config directory,
+// each one as a "<name>.crt" and "<name>.key" PEM pair. Which identity gets
+// used where is recorded in the "bindings" file as "<scope> <name>" lines, the
+// scope being the url the identity covers along with everything under it.
+pub struct IdentityStore {
+    dir: PathBuf,
+    identities: Vec<Identity>,
+    bindings: Vec<(String, String)>,
+}
+
+impl IdentityStore {
+    pub fn load() -> Result<IdentityStore> {
+        let dir = crate::config_dir().join("identities");
+        let mut store = IdentityStore {
+            dir,
+            identities: Vec::new(),
+            bindings: Vec::new(),
+        };
+
+        let entries = match fs::read_dir(&store.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(store),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read {}", store.dir.display()))
+            }
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != "crt") {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            match load_pem_pair(&path, &path.with_extension("key")) {
+                Ok((certificates, key)) => store.identities.push(Identity {
+                    name,
+                    certificates,
+                    key,
+                }),
+                Err(err) => eprintln!("Skipping identity {name}: {err}"),
+            }
+        }
+        store.identities.sort_by(|a, b| a.name.cmp(&b.name));
+
+        match fs::read_to_string(store.dir.join("bindings")) {
+            Ok(contents) => {
+                for line in contents.lines() {
+                    if let Some((prefix, name)) = line.trim().split_once(' ') {
+                        store.bindings.push((prefix.to_string(), name.to_string()));
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err).context("Failed to read identity bindings"),
+        }
+
+        Ok(store)
+    }
+
+    pub fn identities(&self) -> &[Identity] {
+        &self.identities
+    }
+
+    // Creates a new self signed identity with name as its common name
+    pub fn generate(&mut self, name: &str) -> Result<()> {
+        self.check_name(name)?;
+        let mut params = rcgen::CertificateParams::new(vec![name.to_string()]);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, name);
+        params.not_after = rcgen::date_time_ymd(9999, 12, 31);
+        let certificate = rcgen::Certificate::from_params(params)
+            .context("Failed to generate certificate")?;
+
+        self.write_pem_pair(
+            name,
+            &certificate.serialize_pem()?,
+            &certificate.serialize_private_key_pem(),
+        )?;
+        self.identities.push(Identity {
+            name: name.to_string(),
+            certificates: vec![Certificate(certificate.serialize_der()?)],
+            key: PrivateKey(certificate.serialize_private_key_der()),
+        });
+        self.identities.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(())
+    }
+
+    // Copies an existing PEM encoded certificate and key into the store
+    pub fn import(&mut self, name: &str, certificate_path: &Path, key_path: &Path) -> Result<()> {
+        self.check_name(name)?;
+        let (certificates, key) = load_pem_pair(certificate_path, key_path)?;
+        let certificate_pem = fs::read_to_string(certificate_path)?;
+        let key_pem = fs::read_to_string(key_path)?;
+
+        self.write_pem_pair(name, &certificate_pem, &key_pem)?;
+        self.identities.push(Identity {
+            name: name.to_string(),
+            certificates,
+            key,
+        });
+        self.identities.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(())
+    }
+
+    // Uses the identity called name for every url within scope
+    pub fn bind(&mut self, scope: &str, name: &str) -> Result<()> {
+        self.bindings.retain(|(bound, _)| bound != scope);
+        self.bindings.push((scope.to_string(), name.to_string()));
+        self.save_bindings()
+    }
+
+    // Stops using whichever identity was bound to scope
+    pub fn unbind(&mut self, scope: &str) -> Result<()> {
+        self.bindings.retain(|(bound, _)| bound != scope);
+        self.save_bindings()
+    }
+
+    // The scopes the identity called name is used for
+    pub fn scopes(&self, name: &str) -> Vec<&str> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| bound == name)
+            .map(|(scope, _)| scope.as_str())
+            .collect()
+    }
+
+    // Removes the identity called name for good, files and all, and stops
+    // using it everywhere it was
+    pub fn delete(&mut self, name: &str) -> Result<()> {
+        for extension in ["crt", "key"] {
+            let path = self.dir.join(format!("{name}.{extension}"));
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("Failed to delete {}", path.display()))
+                }
+            }
+        }
+        self.identities.retain(|identity| identity.name != name);
+        self.bindings.retain(|(_, bound)| bound != name);
+        self.save_bindings()
+    }
+
+    // Finds the identity bound to the narrowest scope url is within
+    pub fn for_url(&self, url: &str) -> Option<&Identity> {
+        let (_, name) = self
+            .bindings
+            .iter()
+            .filter(|(scope, _)| in_scope(scope, url))
+            .max_by_key(|(scope, _)| scope.len())?;
+        self.identities.iter().find(|identity| &identity.name == name)
+    }
+
+    fn check_name(&self, name: &str) -> Result<()> {
+        if name.is_empty() || name.contains(|c: char| c == '/' || c == '\\' || c.is_whitespace()) {
+            return Err(anyhow!("Identity names can't be empty or contain slashes or spaces"));
+        }
+        if self.identities.iter().any(|identity| identity.name == name) {
+            return Err(anyhow!("An identity called {name} already exists"));
+        }
+        Ok(())
+    }
+
+    // Keys are only readable by the user, even if an old file was left behind
+    fn write_pem_pair(&self, name: &str, certificate: &str, key: &str) -> Result<()> {
+        self.create_dir()?;
+        fs::write(self.dir.join(format!("{name}.crt")), certificate)
+            .context("Failed to save certificate")?;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(self.dir.join(format!("{name}.key")))
+            .context("Failed to save key")?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .and_then(|_| file.write_all(key.as_bytes()))
+            .context("Failed to save key")
+    }
+
+    // Only the user gets to see what's in the directory
+    fn create_dir(&self) -> Result<()> {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))
+    }
+
+    fn save_bindings(&self) -> Result<()> {
+        self.create_dir()?;
+        let mut contents = String::new();
+        for (prefix, name) in &self.bindings {
+            contents += &format!("{prefix} {name}\n");
+        }
+        fs::write(self.dir.join("bindings"), contents).context("Failed to save identity bindings")
+    }
+}
+
+// The scope an identity picked for url covers by default, the page and
+// anything under it. The path always ends in a slash.
+pub fn default_scope(url: &str) -> Result<String> {
+    let mut scope = url::Url::parse(url)?;
+    scope.set_query(None);
+    scope.set_fragment(None);
+    if !scope.path().ends_with('/') {
+        let path = format!("{}/", scope.path());
+        scope.set_path(&path);
+    }
+    Ok(scope.to_string())
+}
+
+// Whether url is within scope, on the same scheme, host and port and with
+// scope's path or one under it. Paths are compared a segment at a time so
+// "/app" doesn't cover "/apple".
+fn in_scope(scope: &str, url: &str) -> bool {
+    let (scope, url) = match (url::Url::parse(scope), url::Url::parse(url)) {
+        (Ok(scope), Ok(url)) => (scope, url),
+        _ => return false,
+    };
+    let port = |url: &url::Url| url.port().unwrap_or(crate::client::DEFAULT_PORT);
+    if scope.scheme() != url.scheme()
+        || scope.host_str() != url.host_str()
+        || port(&scope) != port(&url)
+    {
+        return false;
+    }
+    let scope_path = scope.path().trim_end_matches('/');
+    let path = url.path();
+    path == scope_path
+        || path
+            .strip_prefix(scope_path)
+            .map_or(false, |rest| rest.starts_with('/'))
+}
+
+fn load_pem_pair(certificate_path: &Path, key_path: &Path) -> Result<(Vec<Certificate>, PrivateKey)> {
+    let mut reader = BufReader::new(
+        fs::File::open(certificate_path)
+            .with_context(|| format!("Failed to open {}", certificate_path.display()))?,
+    );
+    let certificates: Vec<_> = rustls_pemfile::certs(&mut reader)
+        .context("Failed to parse certificate")?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certificates.is_empty() {
+        return Err(anyhow!("No certificate found in {}", certificate_path.display()));
+    }
+
+    let mut reader = BufReader::new(
+        fs::File::open(key_path).with_context(|| format!("Failed to open {}", key_path.display()))?,
+    );
+    let key = rustls_pemfile::read_all(&mut reader)
+        .context("Failed to parse key")?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("No private key found in {}", key_path.display()))?;
+
+    Ok((certificates, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn look_alike_hosts_are_out_of_scope() {
+        let scope = default_scope("gemini://host?query").unwrap();
+        assert_eq!(scope, "gemini://host/");
+        assert!(in_scope(&scope, "gemini://host/page"));
+        assert!(in_scope(&scope, "gemini://host:1965/"));
+        assert!(!in_scope(&scope, "gemini://host.evil.net/"));
+        assert!(!in_scope(&scope, "gemini://hostile.org/"));
+        assert!(!in_scope(&scope, "gemini://host:1966/"));
+        assert!(!in_scope(&scope, "spartan://host/"));
+    }
+
+    #[test]
+    fn paths_match_by_whole_segments() {
+        let scope = default_scope("gemini://host/app").unwrap();
+        assert_eq!(scope, "gemini://host/app/");
+        assert!(in_scope(&scope, "gemini://host/app"));
+        assert!(in_scope(&scope, "gemini://host/app/post?draft"));
+        assert!(!in_scope(&scope, "gemini://host/apple"));
+        assert!(!in_scope(&scope, "gemini://host/"));
+    }
+}
@@ -0,0 +1,156 @@
+use std::rc::Rc;
+
+use glib::clone;
+use gtk::{
+    prelude::*, ApplicationWindow, ButtonsType, CheckButton, Entry, Label, MessageDialog,
+    ScrolledWindow, Stack, TextView,
+};
+use gtk4 as gtk;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+// the spec caps request urls at 1024 bytes
+pub const MAX_URL_LEN: usize = 1024;
+
+// everything but the unreserved characters gets escaped in a query
+const QUERY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// url with input as its query, in place of any it already had. Fragments
+// aren't sent to servers so that goes too.
+pub fn input_url(url: &str, input: &str) -> Result<String, url::ParseError> {
+    let mut url = url::Url::parse(url)?;
+    url.set_fragment(None);
+    url.set_query(Some(&utf8_percent_encode(input, QUERY).to_string()));
+    Ok(url.to_string())
+}
+
+// How long the url carrying input for url may get. Spartan input goes after
+// the request as a data block rather than in the url, so it has no limit.
+fn url_limit(url: &str) -> Option<usize> {
+    if url.starts_with("spartan://") {
+        None
+    } else {
+        Some(MAX_URL_LEN)
+    }
+}
+
+// Asks for the input a page wants, prompt being the meta of its response.
+// Returns the url to request with the input, None if the user cancelled.
+pub async fn input_modal(
+    window: &ApplicationWindow,
+    url: &str,
+    prompt: &str,
+    sensitive: bool,
+) -> Option<String> {
+    let dialog = MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .buttons(ButtonsType::OkCancel)
+        .text(prompt)
+        .build();
+    dialog.set_default_response(gtk::ResponseType::Ok);
+
+    let entry = Entry::builder().activates_default(true).build();
+    if sensitive {
+        entry.set_visibility(false);
+        entry.set_invisible_char(Some('*'));
+    }
+    // long input like guestbook posts is easier to write over several lines
+    let text_view = TextView::builder().wrap_mode(gtk::WrapMode::WordChar).build();
+    let scroll = ScrolledWindow::builder()
+        .child(&text_view)
+        .min_content_width(400)
+        .min_content_height(150)
+        .build();
+    let stack = Stack::new();
+    stack.add_named(&entry, Some("line"));
+    stack.add_named(&scroll, Some("lines"));
+    let multi_line = CheckButton::with_label("Multiple lines");
+    let counter = Label::builder().xalign(1.0).build();
+
+    let content = dialog.content_area();
+    content.append(&stack);
+    if !sensitive {
+        content.append(&multi_line);
+    }
+    content.append(&counter);
+
+    let text = Rc::new(
+        clone!(@weak entry, @weak text_view, @weak multi_line => @default-return String::new(), move || {
+            if multi_line.is_active() {
+                let buffer = text_view.buffer();
+                buffer.text(&buffer.start_iter(), &buffer.end_iter(), false).to_string()
+            } else {
+                entry.text().to_string()
+            }
+        }),
+    );
+    // the whole url counts towards the limit, escapes and all
+    let url = url.to_string();
+    let limit = url_limit(&url);
+    let update = Rc::new(clone!(@weak dialog, @weak counter, @strong text, @strong url => move || {
+        match limit {
+            Some(limit) => {
+                let len = input_url(&url, &text()).map_or(0, |url| url.len());
+                counter.set_text(&format!("{len} / {limit} bytes"));
+                dialog.set_response_sensitive(gtk::ResponseType::Ok, len <= limit);
+            }
+            None => counter.set_text(&format!("{} bytes", text().len())),
+        }
+    }));
+
+    entry.connect_changed(clone!(@strong update => move |_| update()));
+    text_view.buffer().connect_changed(clone!(@strong update => move |_| update()));
+    multi_line.connect_toggled(clone!(@weak entry, @weak text_view, @weak stack, @strong update => move |check| {
+        let buffer = text_view.buffer();
+        if check.is_active() {
+            buffer.set_text(&entry.text());
+            stack.set_visible_child_name("lines");
+            text_view.grab_focus();
+        } else {
+            entry.set_text(&buffer.text(&buffer.start_iter(), &buffer.end_iter(), false));
+            stack.set_visible_child_name("line");
+            entry.grab_focus();
+        }
+        update();
+    }));
+    update();
+
+    let response = dialog.run_future().await;
+    let input = text();
+    dialog.close();
+    if response != gtk::ResponseType::Ok {
+        return None;
+    }
+    input_url(&url, &input).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_query_is_escaped() {
+        assert_eq!(
+            input_url("gemini://example.org/search", "hello world/ä?").unwrap(),
+            "gemini://example.org/search?hello%20world%2F%C3%A4%3F"
+        );
+    }
+
+    #[test]
+    fn replaces_query_and_drops_fragment() {
+        assert_eq!(
+            input_url("gemini://example.org/post?draft#top", "a-b_c.d~").unwrap(),
+            "gemini://example.org/post?a-b_c.d~"
+        );
+    }
+
+    #[test]
+    fn spartan_input_has_no_url_limit() {
+        assert_eq!(url_limit("gemini://example.org/post"), Some(MAX_URL_LEN));
+        assert_eq!(url_limit("spartan://example.org/post"), None);
+    }
+}
@@ -1,63 +1,189 @@
 const DEFAULT_URL: &str = "gemini://gemini.circumlunar.space/";
+// redirects followed in a row before giving up, as suggested by the spec
+const MAX_REDIRECTS: usize = 5;
 
-use std::cell::RefCell;
+mod about;
+mod bookmarks;
+mod browser;
+mod cache;
+mod cancel;
+mod cli;
+mod client;
+mod find;
+mod finger;
+mod gopher;
+mod header;
+mod history;
+mod history_view;
+mod identities_view;
+mod identity;
+mod input;
+mod mime;
+mod page_info;
+mod preferences;
+mod profile;
+mod session;
+mod settings;
+mod shortcuts;
+mod spartan;
+mod spool;
+mod stream;
+mod tab;
+mod titan;
+mod tofu;
+
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use async_recursion::async_recursion;
-use glib::{clone, MainContext, Sender, PRIORITY_DEFAULT};
+use glib::clone;
 use gtk::{
-    prelude::*, Builder, Button, ButtonsType, Entry, MessageDialog, TextBuffer,
-    TextChildAnchor, TextTag, TextTagTable, TextView,
+    prelude::*, Builder, Button, ButtonsType, ComboBoxText, Dialog, DialogFlags, Entry,
+    FileChooserAction, FileChooserDialog, Label, MessageDialog, Notebook, Orientation, PackType,
+    Picture, TextBuffer, TextChildAnchor, TextIter, TextTag, TextTagTable, TextView,
 };
-use gtk::{Application, ApplicationWindow};
+use gtk::{gio, Application, ApplicationWindow};
 use gtk4 as gtk;
-use leda::gemini::{self, gemtext, Gemtext};
-use percent_encoding::utf8_percent_encode;
 
-// program state
+use bookmarks::{Bookmark, Bookmarks};
+use browser::Browser;
+use cancel::Cancel;
+use client::{Client, Progress};
+use gopher::MenuItem;
+use header::Header;
+use mime::Mime;
+use profile::Profile;
+use session::SessionHistory;
+use settings::{RedirectPolicy, Settings, Startup};
+use spool::Spool;
+use stream::{Line, LineSplitter, Parser};
+
+// per tab program state
 #[derive(Clone)]
-struct Castor {
-    current_url: String,
-    history: Vec<String>,
-    history_index: usize,
+pub struct Castor {
+    history: SessionHistory,
 }
 
 impl Castor {
-    pub fn new() -> Castor {
+    pub fn with_url(url: String) -> Castor {
         Castor {
-            current_url: String::from(DEFAULT_URL),
-            history: vec![String::from(DEFAULT_URL)],
-            history_index: 0,
+            history: SessionHistory::new(url),
         }
     }
+
+    pub fn current_url(&self) -> String {
+        self.history.current().url.clone()
+    }
+}
+
+// directory castor keeps its configuration in
+pub fn config_dir() -> PathBuf {
+    glib::user_config_dir().join("castor")
+}
+
+// directory castor keeps records like the browsing history in
+pub fn data_dir() -> PathBuf {
+    glib::user_data_dir().join("castor")
+}
+
+// A page load_page managed to display
+#[derive(Clone)]
+pub struct Page {
+    pub url: String,
+    pub title: Option<String>,
+    // urls that redirected to this one, oldest first
+    pub redirects: Vec<String>,
+    // what the page was shown from, kept for viewing its source and saving it
+    pub response: client::Response,
 }
 
 fn main() {
+    let command = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("castor: {err}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    // a url to browse is read again by whichever castor ends up opening it
+    match command {
+        cli::Command::Help => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        cli::Command::Dump { url, render } => {
+            if let Err(err) = cli::dump(&url, render) {
+                eprintln!("castor: {err:#}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        cli::Command::Browse(_) => {}
+    }
+
     let app = Application::builder()
         .application_id("com.github.maebee-cm.dioscuri.castor")
+        .flags(gio::ApplicationFlags::HANDLES_COMMAND_LINE)
         .build();
 
-    app.connect_activate(|app| {
-        let window = match build_ui(&app) {
-            Ok(window) => window,
-            Err(e) => {
-                eprintln!("Error occurred while creating ui: {}", e);
-                return;
-            }
+    // loaded when the first window opens, every window after that shares it
+    let profile: Rc<RefCell<Option<Rc<Profile>>>> = Rc::default();
+    // only one castor runs at a time, running it again hands the arguments
+    // over to the first one, which opens a window for the url asked for
+    app.connect_command_line(clone!(@strong profile => move |app, command_line| {
+        let args = command_line
+            .arguments()
+            .into_iter()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().to_string());
+        // anything but browsing was dealt with before the arguments got here
+        let start_url = match cli::parse_args(args) {
+            Ok(cli::Command::Browse(url)) => url,
+            _ => None,
         };
-        window.show();
+        if open_window(app, &profile, start_url) {
+            0
+        } else {
+            1
+        }
+    }));
+    app.connect_activate(move |app| {
+        open_window(app, &profile, None);
     });
 
-    app.run();
+    app.run_with_args(&std::env::args().collect::<Vec<_>>());
 }
 
-fn build_ui(app: &Application) -> Result<ApplicationWindow> {
-    let client = Rc::new(RefCell::new(
-        gemini::Client::new().context("Failed to create gemini client")?,
-    ));
-    let castor_state = Rc::new(RefCell::new(Castor::new()));
+// Opens a window showing start_url, loading the profile if it's the first
+// one. Returns false if the profile couldn't be loaded.
+fn open_window(
+    app: &Application,
+    profile: &RefCell<Option<Rc<Profile>>>,
+    start_url: Option<String>,
+) -> bool {
+    let loaded = profile.borrow().clone();
+    let loaded = match loaded {
+        Some(loaded) => loaded,
+        None => match Profile::load() {
+            Ok(loaded) => profile.borrow_mut().insert(Rc::new(loaded)).clone(),
+            Err(e) => {
+                eprintln!("Error occurred while creating ui: {:#}", e);
+                return false;
+            }
+        },
+    };
+    build_ui(app, &loaded, start_url).show();
+    true
+}
 
+fn build_ui(
+    app: &Application,
+    profile: &Rc<Profile>,
+    start_url: Option<String>,
+) -> ApplicationWindow {
     let ui_src = include_str!("../assets/castor.ui");
     let builder = Builder::from_string(ui_src);
 
@@ -67,17 +193,99 @@ fn build_ui(app: &Application) -> Result<ApplicationWindow> {
     let forward_button: Button = builder.object("forward_button").expect("Couldn't get forward button");
     let refresh_button: Button = builder.object("refresh_button").expect("Couldn't get refresh button");
     let url_bar: Entry = builder.object("url_bar").expect("Couldn't get url bar");
-    let page_content: TextView = builder.object("page_content").expect("Couldn't get page content");
+    let tabs: Notebook = builder.object("tabs").expect("Couldn't get tabs");
+
+    let tab_actions = gtk::Box::new(Orientation::Horizontal, 0);
+    tab_actions.append(
+        &Button::builder()
+            .label("+")
+            .tooltip_text("New tab")
+            .action_name("win.new-tab")
+            .build(),
+    );
+    tab_actions.append(
+        &Button::builder()
+            .label("↶")
+            .tooltip_text("Reopen closed tab")
+            .action_name("win.reopen-closed-tab")
+            .build(),
+    );
+    tabs.set_action_widget(&tab_actions, PackType::End);
+
+    let browser = Browser::new(&builder, profile.clone());
+    let weak = Rc::downgrade(&browser);
+
+    // this handler lives as long as the window does, so it's the one that
+    // keeps the browser alive
+    window.connect_show(clone!(@strong browser => move |_w| {
+        if browser.current_tab().is_some() {
+            return;
+        }
+        let (startup, home_page) = {
+            let settings = browser.profile.settings.borrow();
+            (settings.startup, settings.home_page.clone())
+        };
+        let mut urls = match (&start_url, startup) {
+            (Some(url), _) => vec![url.clone()],
+            (None, Startup::Home) => vec![home_page.clone()],
+            (None, Startup::Blank) => vec![String::from("about:blank")],
+            (None, Startup::RestoreSession) => settings::load_session(),
+        };
+        if urls.is_empty() {
+            urls.push(home_page);
+        }
+        for (i, url) in urls.into_iter().enumerate() {
+            browser.open_tab(Castor::with_url(url), i == 0);
+        }
+    }));
 
+    url_bar.connect_activate(clone!(@strong weak => move |entry| {
+        if let Some(browser) = weak.upgrade() {
+            if let Some(tab) = browser.current_tab() {
+                browser.navigate(&tab, entry.buffer().text().to_string());
+            }
+        }
+    }));
+
+    back_button.connect_clicked(clone!(@strong weak => move |_| {
+        if let Some(browser) = weak.upgrade() {
+            if let Some(tab) = browser.current_tab() {
+                browser.go_back(&tab);
+            }
+        }
+    }));
+
+    forward_button.connect_clicked(clone!(@strong weak => move |_| {
+        if let Some(browser) = weak.upgrade() {
+            if let Some(tab) = browser.current_tab() {
+                browser.go_forward(&tab);
+            }
+        }
+    }));
+
+    refresh_button.connect_clicked(clone!(@strong weak => move |_| {
+        if let Some(browser) = weak.upgrade() {
+            if let Some(tab) = browser.current_tab() {
+                browser.refresh(&tab);
+            }
+        }
+    }));
+
+    window
+}
+
+// The text tags pages are styled with
+fn text_tags() -> TextTagTable {
     let tag_table = TextTagTable::new();
     tag_table.add(&TextTag::builder().name("plaintext").build());
     tag_table.add(
         &TextTag::builder()
             .name("link")
-            .foreground("blue")
             .underline(gtk::pango::Underline::Single)
             .build(),
     );
+    // added after "link" so its colour takes priority
+    tag_table.add(&TextTag::builder().name("visited").build());
     tag_table.add(&TextTag::builder().name("header").size_points(20.0).build());
     tag_table.add(
         &TextTag::builder()
@@ -91,180 +299,269 @@ fn build_ui(app: &Application) -> Result<ApplicationWindow> {
             .size_points(12.0)
             .build(),
     );
-    tag_table.add(&TextTag::builder().name("preformatted").build());
-    let buffer = TextBuffer::new(Some(&tag_table));
-    page_content.set_buffer(Some(&buffer));
-
-    // we'll use this when the user clicks on a links
-    let (tx, rx) = MainContext::channel::<String>(PRIORITY_DEFAULT);
-
-    window.connect_show(clone!(@strong client, @strong castor_state, @weak page_content, @weak window, @strong tx => move |_w| {
-        let main_context = MainContext::default();
-        main_context.spawn_local(clone!(@weak client, @strong castor_state, @weak page_content, @strong tx => async move {
-            load_page(&mut client.borrow_mut(), castor_state.borrow_mut().current_url.clone(), String::from(DEFAULT_URL), &page_content, &window, tx.clone()).await;
-        }));
-    }));
-
-    url_bar.connect_activate(clone!(@strong castor_state, @strong client, @weak page_content, @weak window,
-        @weak forward_button, @weak back_button, @strong tx => move |entry| {
-        let url = entry.buffer().text().to_string();
-        forward_button.set_sensitive(false);
-        back_button.set_sensitive(true);
-
-        let main_context = MainContext::default();
-        main_context.spawn_local(clone!(@strong castor_state, @strong client, @weak page_content,
-            @weak window, @strong tx, @weak entry => async move {
-            let ret = load_page(&mut client.borrow_mut(), castor_state.borrow_mut().current_url.clone(), url, &page_content, &window, tx.clone()).await;
-            if let Some(url) = ret{
-                castor_state.borrow_mut().current_url = url;
-                entry.set_text(&castor_state.borrow().current_url);
-            }
-            let mut state = castor_state.borrow_mut();
-            state.history_index += 1;
-            // make the borrow checker happy
-            let index = state.history_index;
-            state.history.insert(index, entry.text().to_string());
-            state.history.truncate(index+1);
-        }));
-    }));
+    tag_table.add(
+        &TextTag::builder()
+            .name("quote")
+            .style(gtk::pango::Style::Italic)
+            .left_margin(24)
+            .build(),
+    );
+    // the bullet hangs out to the left so wrapped lines line up with the text
+    tag_table.add(
+        &TextTag::builder()
+            .name("list")
+            .left_margin(24)
+            .indent(-12)
+            .build(),
+    );
+    tag_table.add(&TextTag::builder().name(PROMPT_TAG).build());
+    // ascii art and code keep their layout, the view scrolls sideways instead
+    tag_table.add(
+        &TextTag::builder()
+            .name("preformatted")
+            .wrap_mode(gtk::WrapMode::None)
+            .build(),
+    );
+    tag_table.add(&TextTag::builder().name(ALT_TEXT_TAG).build());
+    tag_table.add(
+        &TextTag::builder()
+            .name("find-match")
+            .background("yellow")
+            .build(),
+    );
+    tag_table.add(
+        &TextTag::builder()
+            .name("find-current")
+            .background("orange")
+            .build(),
+    );
+    tag_table.add(
+        &TextTag::builder()
+            .name("link-number")
+            .foreground("white")
+            .background("gray")
+            .build(),
+    );
+    tag_table.add(
+        &TextTag::builder()
+            .name("line-number")
+            .foreground("gray")
+            .build(),
+    );
+    tag_table
+}
 
-    back_button.connect_clicked(clone!(@strong castor_state, @strong client, @weak page_content,
-        @weak window, @weak forward_button, @weak url_bar, @strong tx => move |button| {
-        let mut state = castor_state.borrow_mut();
-        state.history_index -= 1;
-        if state.history_index == 0 {
-            button.set_sensitive(false);
-        }
-        forward_button.set_sensitive(true);
-        let url = state.history[state.history_index].clone();
-
-        let main_context = MainContext::default();
-        main_context.spawn_local(clone!(@strong castor_state, @strong client, @weak page_content,
-            @weak window, @strong tx, @weak url_bar => async move {
-            let ret = load_page(&mut client.borrow_mut(), castor_state.borrow_mut().current_url.clone(), url, &page_content, &window, tx.clone()).await;
-            if let Some(url) = ret{
-                castor_state.borrow_mut().current_url = url;
-                url_bar.set_text(&castor_state.borrow().current_url);
-            }
-        }));
-    }));
+// Updates the text tags shared by every tab to match settings, pages that are
+// already shown pick the changes up straight away
+pub fn apply_settings(tag_table: &TextTagTable, settings: &Settings) {
+    let family = gtk::pango::FontDescription::from_string(&settings.font).family();
+    for name in ["plaintext", "link", "quote", "list"] {
+        if let Some(tag) = tag_table.lookup(name) {
+            tag.set_font(Some(&settings.font));
+        }
+    }
+    // set_font replaces the whole description, style and all
+    if let Some(tag) = tag_table.lookup("quote") {
+        tag.set_style(gtk::pango::Style::Italic);
+    }
+    for name in ["header", "subheader", "subsubheader"] {
+        if let Some(tag) = tag_table.lookup(name) {
+            tag.set_family(family.as_deref());
+        }
+    }
+    for name in ["preformatted", "line-number"] {
+        if let Some(tag) = tag_table.lookup(name) {
+            tag.set_font(Some(&settings.monospace_font));
+        }
+    }
+    if let Some(tag) = tag_table.lookup("link") {
+        tag.set_foreground(Some(&settings.link_color));
+    }
+    if let Some(tag) = tag_table.lookup("visited") {
+        tag.set_foreground(Some(&settings.visited_link_color));
+    }
+}
 
-    forward_button.connect_clicked(clone!(@strong castor_state, @strong client, @weak page_content,
-        @weak window, @weak back_button, @weak url_bar, @strong tx => move |button| {
-        let mut state = castor_state.borrow_mut();
-        state.history_index += 1;
-        if state.history_index == state.history.len()-1 {
-            button.set_sensitive(false);
-        }
-        back_button.set_sensitive(true);
-        let url = state.history[state.history_index].clone();
-
-        let main_context = MainContext::default();
-        main_context.spawn_local(clone!(@strong castor_state, @strong client, @weak page_content,
-            @weak window, @strong tx, @weak url_bar => async move {
-            let ret = load_page(&mut client.borrow_mut(), castor_state.borrow_mut().current_url.clone(), url, &page_content, &window, tx.clone()).await;
-            if let Some(url) = ret{
-                castor_state.borrow_mut().current_url = url;
-                url_bar.set_text(&castor_state.borrow().current_url);
-            }
-        }));
-    }));
+// Renders a text page into a buffer a line at a time as it arrives, gemtext
+// gets formatted and anything else is shown as it is
+struct PageWriter {
+    text_view: TextView,
+    buffer: TextBuffer,
+    mime: Mime,
+    lines: LineSplitter,
+    parser: Parser,
+    // the first heading, which names the page
+    title: Option<String>,
+    // marks added so far, see add_mark
+    marks: usize,
+}
 
-    refresh_button.connect_clicked(clone!(@strong castor_state, @strong client, @weak page_content,
-        @weak window, @strong tx =>  move |_| {
-        let main_context = MainContext::default();
-        main_context.spawn_local(clone!(@strong castor_state, @strong client, @weak page_content, @weak window, @strong tx => async move {
-            let current_url = castor_state.borrow().current_url.clone();
-            load_page(&mut client.borrow_mut(), current_url.clone(), current_url, &page_content, &window, tx.clone()).await;
-        }));
-    }));
+impl PageWriter {
+    // Shows buffer in text_view, the charset of mime must be a known one. url
+    // is where the page came from.
+    fn new(text_view: &TextView, buffer: &TextBuffer, mime: Mime, url: &str) -> PageWriter {
+        text_view.set_buffer(Some(buffer));
+        PageWriter {
+            text_view: text_view.clone(),
+            buffer: buffer.clone(),
+            mime,
+            lines: LineSplitter::default(),
+            parser: Parser::for_url(url),
+            title: None,
+            marks: 0,
+        }
+    }
 
-    rx.attach(None, clone!(@weak forward_button, @weak back_button, @strong client, @strong castor_state,
-        @weak window, @strong tx, @weak url_bar => @default-return Continue(false), move |url| {
-        forward_button.set_sensitive(false);
-        back_button.set_sensitive(true);
-
-        let main_context = MainContext::default();
-        main_context.spawn_local(clone!(@strong castor_state, @strong client, @weak page_content,
-            @weak window, @strong tx, @weak url_bar => async move {
-            let ret = load_page(&mut client.borrow_mut(), castor_state.borrow_mut().current_url.clone(), url, &page_content, &window, tx.clone()).await;
-            if let Some(url) = ret{
-                castor_state.borrow_mut().current_url = url;
-                url_bar.set_text(&castor_state.borrow().current_url);
-            }
-            let mut state = castor_state.borrow_mut();
-            state.history_index += 1;
-            // make the borrow checker happy
-            let index = state.history_index;
-            state.history.insert(index, url_bar.text().to_string());
-            state.history.truncate(index+1);
-        }));
-        Continue(true)
-    }));
+    fn write(&mut self, chunk: &[u8]) {
+        for line in self.lines.push(chunk) {
+            self.write_line(&line);
+        }
+    }
 
-    Ok(window)
-}
+    // Writes whatever is left and returns the title
+    fn finish(mut self) -> Option<String> {
+        if let Some(line) = self.lines.finish() {
+            self.write_line(&line);
+        }
+        self.title
+    }
 
-fn gemtext_to_text_buffer(gemtext: Gemtext, text_view: &TextView, link_tx: Sender<String>) {
-    let buffer = text_view.buffer();
-    for element in gemtext.elements {
-        match element {
-            gemtext::Element::Text(mut text) => {
-                text += "\n";
-                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["plaintext"]);
+    fn write_line(&mut self, line: &[u8]) {
+        let text = self.mime.decode(line).unwrap_or_default();
+        let buffer = &self.buffer;
+        if self.mime.essence == gopher::MENU_MIME {
+            // menus are often laid out for a fixed width font
+            match gopher::menu_item(&text) {
+                Some(MenuItem { text, url: Some(url) }) => self.insert_link(&url, &text, &["link"]),
+                Some(MenuItem { text, url: None }) => {
+                    buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), &["preformatted"]);
+                }
+                None => {}
             }
-            gemtext::Element::Link(url, text) => {
-                let link = Button::builder().label(&text).tooltip_text(&url).build();
-                let anchor = TextChildAnchor::new();
-                buffer.insert_child_anchor(&mut buffer.end_iter(), &anchor);
-                text_view.add_child_at_anchor(&link, &anchor);
-                buffer.insert(&mut buffer.end_iter(), "\n");
+            return;
+        }
+        if self.mime.essence != "text/gemini" {
+            buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), &["plaintext"]);
+            return;
+        }
 
-                link.connect_clicked(clone!(@strong link_tx => move |button| {
-                    link_tx.send(button.tooltip_text().unwrap().to_string())
-                        .expect("Failed to send url upon click");
-                }));
+        match self.parser.parse(&text) {
+            Line::Text(text) => {
+                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), &["plaintext"]);
             }
-            gemtext::Element::Heading(mut text) => {
-                text += "\n";
-                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["header"]);
+            Line::Link(url, text) => self.insert_link(&url, &text, &["link"]),
+            Line::Prompt(url, text) => self.insert_link(&url, &text, &["link", PROMPT_TAG]),
+            Line::Heading(text) => {
+                if self.title.is_none() {
+                    self.title = Some(text.trim().to_string());
+                }
+                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), &["header"]);
             }
-            gemtext::Element::Subheading(mut text) => {
-                text += "\n";
-                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["subheader"]);
+            Line::Subheading(text) => {
+                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), &["subheader"]);
             }
-            gemtext::Element::Subsubheading(mut text) => {
-                text += "\n";
-                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["subsubheader"]);
+            Line::Subsubheading(text) => {
+                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), &["subsubheader"]);
             }
-            gemtext::Element::UnorderedList(items) => {
-                for mut text in items {
-                    text.insert_str(0, "•");
-                    text += "\n";
-                    buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["plaintext"]);
-                }
+            Line::ListItem(text) => {
+                let text = format!("•  {text}\n");
+                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["list"]);
             }
-            gemtext::Element::BlockQuote(mut text) => {
-                text += "\n";
-                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["plaintext"]);
+            Line::Quote(text) => {
+                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), &["quote"]);
             }
-            gemtext::Element::Preformatted(_alt_text, mut text) => {
-                text += "\n";
-                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &text, &["preformatted"]);
+            Line::PreformattedStart(alt_text) => {
+                if alt_text.is_empty() {
+                    return;
+                }
+                // screen readers can't make sense of ascii art, so the alt text
+                // gets its own labelled widget in front of the block as well as
+                // being shown when hovering over the block itself
+                let icon = gtk::Image::builder()
+                    .icon_name("dialog-information-symbolic")
+                    .tooltip_text(&alt_text)
+                    .build();
+                icon.update_property(&[gtk::accessible::Property::Label(&alt_text)]);
+                let anchor = TextChildAnchor::new();
+                buffer.insert_child_anchor(&mut buffer.end_iter(), &anchor);
+                self.text_view.add_child_at_anchor(&icon, &anchor);
+                buffer.insert(&mut buffer.end_iter(), "\n");
+                // the block goes in after the mark, so it stays at its start
+                let start = buffer.end_iter();
+                self.add_mark(&start, ALT_TEXT_MARK, &alt_text, true);
             }
+            Line::Preformatted(alt_text, text) => {
+                let tags: &[&str] = if alt_text.is_empty() {
+                    &["preformatted"]
+                } else {
+                    &["preformatted", ALT_TEXT_TAG]
+                };
+                buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &(text + "\n"), tags);
+            }
+            Line::PreformattedEnd => {}
         }
     }
+
+    // Links with no text show their url instead
+    fn insert_link(&mut self, url: &str, text: &str, tags: &[&str]) {
+        let buffer = self.buffer.clone();
+        let label = if text.is_empty() { url } else { text };
+        let start = buffer.end_iter().offset();
+        buffer.insert_with_tags_by_name(&mut buffer.end_iter(), label, tags);
+        buffer.insert(&mut buffer.end_iter(), "\n");
+        // link numbers go in front of links, the mark moves past them
+        self.add_mark(&buffer.iter_at_offset(start), HREF_MARK, url, false);
+    }
+
+    // Leaves a mark at iter, named prefix followed by value, for looking up
+    // what the text there is about. Mark names have to be unique within a
+    // buffer, so they're numbered too.
+    fn add_mark(&mut self, iter: &TextIter, prefix: &str, value: &str, left_gravity: bool) {
+        self.marks += 1;
+        let name = format!("{prefix}{}:{value}", self.marks);
+        self.buffer.create_mark(Some(&name), iter, left_gravity);
+    }
+}
+
+// Tag for links that ask for input before following them
+pub const PROMPT_TAG: &str = "prompt";
+
+// Tag for preformatted blocks that have alt text, the text view shows it as
+// a tooltip
+pub const ALT_TEXT_TAG: &str = "alt-text";
+
+// Prefix of the names of the marks at the start of every link, which carry
+// its url. Marks belong to a page's buffer and go away with it.
+pub const HREF_MARK: &str = "href:";
+
+// Prefix of the names of the marks carrying the alt text of a preformatted
+// block, at the start of the block
+pub const ALT_TEXT_MARK: &str = "alt:";
+
+// Replaces the contents of text_view with gemtext castor generated itself
+fn show_gemtext(source: &str, text_view: &TextView) -> Option<String> {
+    let buffer = TextBuffer::new(Some(&text_view.buffer().tag_table()));
+    let mut writer = PageWriter::new(text_view, &buffer, Mime::parse("text/gemini"), "about:");
+    writer.write(source.as_bytes());
+    writer.finish()
 }
 
 enum LoadPageError {
-    RequestFailure(gemini::Error),
-    EmptyBody(gemini::Response),
-    NotGemtext(gemini::Response),
-    GemtextParsing(gemini::Error, gemini::Response),
+    RequestFailure(client::Error),
+    EmptyBody(client::Response),
+    UnsupportedCharset(client::Response),
+    Image(glib::Error),
+    Download(anyhow::Error),
+    Upload(anyhow::Error),
     InvalidUrl(url::ParseError),
-    FailTemporary(gemini::header::FailTemporaryCode),
-    FailPermanent(gemini::header::FailPermanentCode),
-    CertFail(gemini::header::CertFailCode),
+    FailTemporary(header::FailTemporaryCode),
+    FailPermanent(header::FailPermanentCode),
+    CertFail(header::CertFailCode, String),
+    Identity(anyhow::Error),
+    Bookmarks(anyhow::Error),
+    TooManyRedirects(Vec<String>),
+    RedirectLoop(Vec<String>),
+    Timeout(Duration),
 }
 
 impl std::fmt::Display for LoadPageError {
@@ -279,31 +576,33 @@ impl std::fmt::Display for LoadPageError {
                     // they are not errors and therefor load_page wouldn't create
                     // an error for them. However Succeess can result in an error
                     // if the body is empty.
-                    gemini::header::StatusCode::Input(_)
-                    | gemini::header::StatusCode::Redirect(_) => unreachable!(),
-                    gemini::header::StatusCode::Success => {
+                    header::StatusCode::Input(_)
+                    | header::StatusCode::Redirect(_) => unreachable!(),
+                    header::StatusCode::Success => {
                         String::from("Success, but empty response body")
                     }
-                    gemini::header::StatusCode::FailTemporary(_) => {
+                    header::StatusCode::FailTemporary(_) => {
                         format!("Temporary failure: {}", response.header.status)
                     }
-                    gemini::header::StatusCode::FailPermanent(_) => {
+                    header::StatusCode::FailPermanent(_) => {
                         format!("Permanent failure: {}", response.header.status)
                     }
-                    gemini::header::StatusCode::CertFail(_) => {
+                    header::StatusCode::CertFail(_) => {
                         format!("Certificate failure: {}", response.header.status)
                     }
                 }
             }
-            LoadPageError::NotGemtext(response) => {
-                format!("Response wasn't gemtext. Meta: {}", response.header.meta)
+            LoadPageError::UnsupportedCharset(response) => {
+                format!("Response uses an unsupported charset. Meta: {}", response.header.meta)
             }
-            LoadPageError::GemtextParsing(err, _) => {
-                if let gemini::Error::GemtextFormat(_) = err {
-                    format!("Gemtext parsing error: {err}")
-                } else {
-                    unreachable!()
-                }
+            LoadPageError::Image(err) => {
+                format!("Failed to display image: {err}")
+            }
+            LoadPageError::Download(err) => {
+                format!("Failed to save file: {err:#}")
+            }
+            LoadPageError::Upload(err) => {
+                format!("Failed to upload: {err:#}")
             }
             LoadPageError::InvalidUrl(err) => {
                 format!("Failed to parse url: {err}")
@@ -314,174 +613,344 @@ impl std::fmt::Display for LoadPageError {
             LoadPageError::FailPermanent(code) => {
                 format!("Permanent failure: {code}")
             }
-            LoadPageError::CertFail(code) => {
-                format!("Certificate failure: {code}\nCertificates are currently not supported")
+            LoadPageError::CertFail(code, meta) => {
+                format!("Certificate failure: {code}\n{meta}")
+            }
+            LoadPageError::Identity(err) => {
+                format!("Failed to set up identity: {err:#}")
+            }
+            LoadPageError::Bookmarks(err) => {
+                format!("Failed to save bookmarks: {err:#}")
+            }
+            LoadPageError::TooManyRedirects(redirects) => {
+                format!(
+                    "Gave up after {MAX_REDIRECTS} redirects:\n{}",
+                    redirects.join("\n→ ")
+                )
+            }
+            LoadPageError::RedirectLoop(redirects) => {
+                format!("Redirect loop detected:\n{}", redirects.join("\n→ "))
+            }
+            LoadPageError::Timeout(timeout) => {
+                format!("The request timed out after {} seconds", timeout.as_secs())
             }
         };
         write!(f, "{}", to_print)
     }
 }
 
-// Returns the url of the page if loaded with no errors, otherwise returns none
+// Turns a possibly relative url into an absolute one, relative urls are
+// resolved against current_url
+pub fn resolve_url(current_url: &str, url: &str) -> Result<String, url::ParseError> {
+    match url::Url::parse(url) {
+        Ok(_) => Ok(url.to_string()),
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            let base = url::Url::parse(current_url)?;
+            Ok(base.join(url)?.to_string())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Where a redirect from url leads, meta being the redirect's meta. url is
+// added to redirects, the error says why the redirect can't be followed.
+fn redirect_target(
+    url: &str,
+    meta: &str,
+    redirects: &mut Vec<String>,
+) -> Result<String, LoadPageError> {
+    // redirect targets may be relative to the page that redirected
+    let target = resolve_url(url, meta).map_err(LoadPageError::InvalidUrl)?;
+    redirects.push(url.to_string());
+    if redirects.contains(&target) {
+        let mut redirects = redirects.clone();
+        redirects.push(target);
+        return Err(LoadPageError::RedirectLoop(redirects));
+    }
+    if redirects.len() > MAX_REDIRECTS {
+        let mut redirects = redirects.clone();
+        redirects.push(target);
+        return Err(LoadPageError::TooManyRedirects(redirects));
+    }
+    Ok(target)
+}
+
+fn same_host(a: &str, b: &str) -> bool {
+    match (url::Url::parse(a), url::Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.host_str() == b.host_str() && a.port() == b.port(),
+        _ => false,
+    }
+}
+
+// Everything a load needs that stays the same across its redirects and retries
+#[derive(Clone, Copy)]
+struct Loader<'a> {
+    client: &'a Client,
+    settings: &'a RefCell<Settings>,
+    cancel: &'a Cancel,
+    // told how each request is getting on, for the status bar
+    progress: &'a dyn Fn(Progress),
+    text_view: &'a TextView,
+    window: &'a ApplicationWindow,
+}
+
+// Returns the url and title of the page if loaded with no errors, otherwise
+// returns none
 #[async_recursion(?Send)]
 async fn load_page(
-    client: &mut gemini::Client,
+    loader: &Loader<'_>,
     current_url: String,
     mut url: String,
-    text_view: &TextView,
-    window: &ApplicationWindow,
-    link_tx: Sender<String>,
-) -> Option<String> {
-    if let Err(err) = url::Url::parse(&url) {
-        let mut new_url = None;
-        if matches!(err, url::ParseError::RelativeUrlWithoutBase) {
-            let to_join = url::Url::parse(&current_url).unwrap();
-            if let Ok(joined) = to_join.join(&url) {
-                new_url = Some(joined.to_string())
-            };
-        }
-
-        if new_url.is_some() {
-            url = new_url.unwrap();
-        }
-        else {
+    // urls that redirected to this one so far, oldest first
+    redirects: Vec<String>,
+) -> Option<Page> {
+    let Loader {
+        client,
+        settings,
+        cancel,
+        progress,
+        text_view,
+        window,
+    } = *loader;
+    url = match resolve_url(&current_url, &url) {
+        Ok(url) => url,
+        Err(err) => {
             load_page_error_modal(&window, LoadPageError::InvalidUrl(err)).await;
             return None;
         }
-    }
+    };
 
+    // the new buffer only replaces the current page once there is something
+    // to show in it
     let old_buffer = text_view.buffer();
     let buffer = TextBuffer::new(Some(&old_buffer.tag_table()));
-    text_view.set_buffer(Some(&buffer));
-    let result = client.async_request(url.clone()).await;
+    let max_size = settings.borrow().max_page_size as usize * 1024 * 1024;
+
+    // text pages are shown as they arrive, until they get too big to show.
+    // From then on the body goes to a file rather than memory, while the
+    // user is asked whether they want it.
+    let writer = RefCell::new(None);
+    let body = RefCell::new(Vec::new());
+    let spool = RefCell::new(None);
+    let prompt = RefCell::new(None);
+    let on_body = |head: &Header, chunk: &[u8]| {
+        // nothing's shown until there's something to show
+        if head.status != header::StatusCode::Success || chunk.is_empty() {
+            return;
+        }
+        let mut body = body.borrow_mut();
+        let mut spool = spool.borrow_mut();
+        if spool.is_none()
+            && body.len() + chunk.len() > max_size
+            && Mime::parse(&head.meta).is_text()
+        {
+            if writer.borrow_mut().take().is_some() {
+                text_view.set_buffer(Some(&old_buffer));
+            }
+            prompt.replace(Some(LargePagePrompt::show(window, max_size, cancel)));
+            *spool = Some(Spool::create().and_then(|mut file| {
+                file.write(&body)?;
+                Ok(file)
+            }));
+            *body = Vec::new();
+        }
+        if let Some(Ok(file)) = spool.as_mut() {
+            if let Err(err) = file.write(chunk) {
+                *spool = Some(Err(err));
+            }
+        }
+        if spool.is_some() {
+            return;
+        }
+        body.extend_from_slice(chunk);
+        let mut writer = writer.borrow_mut();
+        if writer.is_none() {
+            let mime = Mime::parse(&head.meta);
+            if !mime.is_text() || !mime.has_known_charset() {
+                return;
+            }
+            *writer = Some(PageWriter::new(text_view, &buffer, mime, &url));
+        }
+        if let Some(writer) = writer.as_mut() {
+            writer.write(chunk);
+        }
+    };
+    // titan urls are for sending things, so ask what before connecting
+    let request = if url.starts_with("titan://") {
+        let upload = titan::upload_modal(window, &url).await?;
+        cancel
+            .run(client.upload(url.clone(), &upload, progress, &on_body))
+            .await
+    } else {
+        cancel
+            .run(client.request_with_progress(url.clone(), progress, &on_body))
+            .await
+    };
+    let writer = writer.into_inner();
+    let prompt = prompt.into_inner();
+    // the user can still answer about a page too big to show once it's all
+    // downloaded, but not if it never will be
+    if let Some(prompt) = &prompt {
+        if !matches!(request, Some(Ok(_))) {
+            prompt.close();
+        }
+    }
+    // half a page isn't kept, the one from before comes back. That goes for
+    // stopped loads too, unless whoever cancelled this one has shown
+    // something newer since.
+    if !matches!(request, Some(Ok(_))) && text_view.buffer() == buffer {
+        text_view.set_buffer(Some(&old_buffer));
+    }
+    let result = match request {
+        Some(result) => result,
+        // whoever cancelled the load has moved on, so there's nothing to say
+        None => return None,
+    };
+    let result = result.map(|mut response| {
+        let body = body.into_inner();
+        response.body = if body.is_empty() { None } else { Some(body) };
+        response
+    });
     match result {
         Ok(response) => match response.header.status {
-            gemini::header::StatusCode::Input(code) => {
-                let entry_dialog = MessageDialog::builder()
-                    .transient_for(window)
-                    .buttons(ButtonsType::OkCancel)
-                    .text(&response.header.meta)
-                    .build();
-                let entry = Entry::new();
-                match code {
-                    gemini::header::InputCode::Input => {
-                        entry_dialog.content_area().append(&entry);
-                    }
-                    gemini::header::InputCode::Sensitive => {
-                        entry.set_visibility(false);
-                        entry.set_invisible_char(Some('*'));
-                        entry_dialog.content_area().append(&entry);
-                    }
+            header::StatusCode::Input(code) => {
+                let sensitive = code == header::InputCode::Sensitive;
+                match input::input_modal(window, &url, &response.header.meta, sensitive).await {
+                    Some(url) => load_page(loader, current_url, url, redirects).await,
+                    None => None,
                 }
-                let url = url.clone()
-                    + "?"
-                    + &match entry_dialog.run_future().await {
-                        gtk::ResponseType::Ok => entry.text().to_string(),
-                        gtk::ResponseType::Cancel => return None,
-                        _ => unreachable!(),
-                    };
-                let url = utf8_percent_encode(&url, percent_encoding::NON_ALPHANUMERIC).to_string();
-                load_page(client, current_url, url, text_view, window, link_tx).await
-            }
-            gemini::header::StatusCode::Success => {
-                if response.header.meta.starts_with("text/plaintext") {
-                    match &response.body {
-                        Some(body) => {
-                            let text = String::from_utf8_lossy(body);
-                            buffer.set_text("");
-                            buffer.insert_with_tags_by_name(
-                                &mut buffer.end_iter(),
-                                &text,
-                                &["plaintext"],
-                            );
-                            Some(url)
-                        }
-                        None => {
-                            load_page_error_modal(window, LoadPageError::EmptyBody(response)).await;
-                            None
+            }
+            header::StatusCode::Success => {
+                if let Some(prompt) = prompt {
+                    if prompt.answer().await {
+                        let saved = match spool.into_inner() {
+                            Some(Ok(spool)) => save_spool_modal(window, &url, spool).await,
+                            Some(Err(err)) => Err(err),
+                            None => Ok(()),
+                        };
+                        if let Err(err) = saved {
+                            load_page_error_modal(window, LoadPageError::Download(err)).await;
                         }
                     }
-                } else if response.header.meta.starts_with("text/gemini")
-                    || response.header.meta.is_empty()
-                {
-                    match &response.body {
-                        Some(body) => {
-                            let text = String::from_utf8_lossy(&body);
-                            match Gemtext::new(&text) {
-                                Ok(gemtext) => {
-                                    gemtext_to_text_buffer(gemtext, &text_view, link_tx);
-                                    Some(url)
-                                }
-                                Err(err) => {
-                                    load_page_error_modal(
-                                        window,
-                                        LoadPageError::GemtextParsing(err, response),
-                                    )
-                                    .await;
-                                    None
-                                }
-                            }
-                        }
+                    return None;
+                }
+                let body = match &response.body {
+                    Some(body) => body,
+                    None => {
+                        load_page_error_modal(window, LoadPageError::EmptyBody(response)).await;
+                        return None;
+                    }
+                };
+                let mime = Mime::parse(&response.header.meta);
+                if mime.is_text() {
+                    if !mime.has_known_charset() {
+                        load_page_error_modal(window, LoadPageError::UnsupportedCharset(response)).await;
+                        return None;
+                    }
+                    let writer = match writer {
+                        Some(writer) => writer,
                         None => {
-                            load_page_error_modal(window, LoadPageError::EmptyBody(response)).await;
-                            None
+                            let mut writer = PageWriter::new(text_view, &buffer, mime, &url);
+                            writer.write(body);
+                            writer
                         }
-                    }
+                    };
+                    Some(Page {
+                        url,
+                        title: writer.finish(),
+                        redirects,
+                        response,
+                    })
+                } else if mime.is_image() {
+                    let texture = match image_texture(body) {
+                        Ok(texture) => texture,
+                        Err(err) => {
+                            load_page_error_modal(window, LoadPageError::Image(err)).await;
+                            return None;
+                        }
+                    };
+                    show_image(text_view, &buffer, &texture, &url);
+                    Some(Page {
+                        url,
+                        title: None,
+                        redirects,
+                        response,
+                    })
                 } else {
-                    load_page_error_modal(window, LoadPageError::NotGemtext(response)).await;
+                    // anything we can't show gets offered as a download, the
+                    // current page stays as it is
+                    if let Err(err) = save_modal(window, &url, body).await {
+                        load_page_error_modal(window, LoadPageError::Download(err)).await;
+                    }
                     None
                 }
             }
-            gemini::header::StatusCode::Redirect(code) => {
-                let redirect_dialog_builder = MessageDialog::builder()
-                    .transient_for(window)
-                    .modal(true)
-                    .buttons(ButtonsType::YesNo);
-                let user_response = match code {
-                    gemini::header::RedirectCode::Temporary => {
-                        let redirect_dialog = redirect_dialog_builder
-                            .text(&format!("This website has a temporary redirect to {}\nWould you like to continue?", response.header.meta))
-                            .build();
-                        let user_response = redirect_dialog.run_future().await;
-                        redirect_dialog.close();
-                        user_response
-                    }
-                    gemini::header::RedirectCode::Permanent => {
-                        let redirect_dialog = redirect_dialog_builder
-                            .text(&format!("This website has a permanent redirect to {}\nWould you like to continue?", response.header.meta))
-                            .build();
-                        let user_response = redirect_dialog.run_future().await;
-                        redirect_dialog.close();
-                        user_response
+            header::StatusCode::Redirect(code) => {
+                let mut redirects = redirects;
+                let target = match redirect_target(&url, &response.header.meta, &mut redirects) {
+                    Ok(target) => target,
+                    Err(err) => {
+                        load_page_error_modal(window, err).await;
+                        return None;
                     }
                 };
-                if matches!(user_response, gtk::ResponseType::Yes) {
-                    load_page(
-                        client,
-                        current_url,
-                        response.header.meta,
-                        text_view,
-                        window,
-                        link_tx,
-                    )
-                    .await
+                if follow_redirect(settings, window, code, &url, &target).await {
+                    load_page(loader, current_url, target, redirects).await
                 } else {
                     None
                 }
             }
-            gemini::header::StatusCode::FailTemporary(code) => {
+            header::StatusCode::FailTemporary(code) => {
                 load_page_error_modal(window, LoadPageError::FailTemporary(code)).await;
                 None
             }
-            gemini::header::StatusCode::FailPermanent(code) => {
+            header::StatusCode::FailPermanent(code) => {
                 load_page_error_modal(window, LoadPageError::FailPermanent(code)).await;
                 None
             }
-            gemini::header::StatusCode::CertFail(code) => {
-                load_page_error_modal(window, LoadPageError::CertFail(code)).await;
+            header::StatusCode::CertFail(header::CertFailCode::CertificateRequired) => {
+                match identity_modal(client, window, &url, &response.header.meta).await {
+                    Ok(true) => {
+                        load_page(loader, current_url, url, redirects).await
+                    }
+                    Ok(false) => None,
+                    Err(err) => {
+                        load_page_error_modal(window, LoadPageError::Identity(err)).await;
+                        None
+                    }
+                }
+            }
+            header::StatusCode::CertFail(code) => {
+                load_page_error_modal(
+                    window,
+                    LoadPageError::CertFail(code, response.header.meta),
+                )
+                .await;
                 None
             }
         },
+        // links to the web and the like are for other programs
+        Err(client::Error::UnsupportedScheme(scheme)) => {
+            if let Err(err) =
+                gio::AppInfo::launch_default_for_uri(&url, None::<&gio::AppLaunchContext>)
+            {
+                eprintln!("Failed to open {url}: {err}");
+                let err = client::Error::UnsupportedScheme(scheme);
+                load_page_error_modal(window, LoadPageError::RequestFailure(err)).await;
+            }
+            None
+        }
+        Err(client::Error::Timeout(timeout)) => {
+            load_page_error_modal(window, LoadPageError::Timeout(timeout)).await;
+            None
+        }
+        Err(client::Error::CertificateChanged(mismatch)) => {
+            if certificate_changed_modal(client, window, mismatch).await {
+                load_page(loader, current_url, url, redirects).await
+            } else {
+                None
+            }
+        }
         Err(e) => {
             load_page_error_modal(window, LoadPageError::RequestFailure(e)).await;
             None
@@ -489,6 +958,516 @@ async fn load_page(
     }
 }
 
+// Whether to follow a redirect from url to target, going by the settings or
+// asking the user if they don't say
+async fn follow_redirect(
+    settings: &RefCell<Settings>,
+    window: &ApplicationWindow,
+    code: header::RedirectCode,
+    url: &str,
+    target: &str,
+) -> bool {
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    let (policy, rule) = {
+        let settings = settings.borrow();
+        (settings.redirect_policy, settings.redirect_rules.get(&host).copied())
+    };
+    match (rule, policy) {
+        (Some(follow), _) => follow,
+        (None, RedirectPolicy::Always) => true,
+        (None, RedirectPolicy::SameHost) if same_host(url, target) => true,
+        _ => redirect_modal(window, settings, code, &host, target).await,
+    }
+}
+
+// Saves what url responds with to a file without showing it, following
+// redirects like load_page does. The body goes to a temporary file as it
+// arrives rather than into memory, and is moved wherever the user picks once
+// it's all in.
+#[async_recursion(?Send)]
+async fn download(loader: &Loader<'_>, url: String, mut redirects: Vec<String>) {
+    let Loader {
+        client,
+        settings,
+        cancel,
+        progress,
+        window,
+        ..
+    } = *loader;
+    let spool = RefCell::new(None);
+    let on_body = |head: &Header, chunk: &[u8]| {
+        if head.status != header::StatusCode::Success {
+            return;
+        }
+        let mut spool = spool.borrow_mut();
+        let entry = spool.get_or_insert_with(Spool::create);
+        if let Ok(file) = entry {
+            if let Err(err) = file.write(chunk) {
+                *entry = Err(err);
+            }
+        }
+    };
+    let response = match cancel
+        .run(client.request_with_progress(url.clone(), progress, &on_body))
+        .await
+    {
+        Some(Ok(response)) => response,
+        Some(Err(err)) => {
+            load_page_error_modal(window, LoadPageError::RequestFailure(err)).await;
+            return;
+        }
+        // stopped, or something else started loading in the tab
+        None => return,
+    };
+    let saved = match response.header.status {
+        header::StatusCode::Success => match spool.into_inner() {
+            Some(Ok(spool)) => save_spool_modal(window, &url, spool).await,
+            Some(Err(err)) => Err(err),
+            None => Ok(()),
+        },
+        header::StatusCode::Redirect(code) => {
+            match redirect_target(&url, &response.header.meta, &mut redirects) {
+                Ok(target) => {
+                    if follow_redirect(settings, window, code, &url, &target).await {
+                        download(loader, target, redirects).await;
+                    }
+                }
+                Err(err) => load_page_error_modal(window, err).await,
+            }
+            return;
+        }
+        _ => Err(anyhow::anyhow!("{url} responded with {}", response.header)),
+    };
+    if let Err(err) = saved {
+        load_page_error_modal(window, LoadPageError::Download(err)).await;
+    }
+}
+
+// Asks the user what to do about a host whose certificate no longer matches
+// the one on record. Returns true if the load should be retried.
+async fn certificate_changed_modal(
+    client: &Client,
+    window: &ApplicationWindow,
+    mismatch: tofu::Mismatch,
+) -> bool {
+    let dialog = MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Warning)
+        .buttons(ButtonsType::None)
+        .text(&format!("The certificate for {} has changed", mismatch.host))
+        .secondary_text(&format!(
+            "This could mean the capsule renewed its certificate, or that someone is \
+            intercepting your connection.\n\nKnown fingerprint:\n{}\n\nPresented fingerprint:\n{}",
+            mismatch.known, mismatch.presented
+        ))
+        .build();
+    dialog.add_button("Stop loading", gtk::ResponseType::Cancel);
+    dialog.add_button("Accept once", gtk::ResponseType::Apply);
+    dialog.add_button("Accept permanently", gtk::ResponseType::Accept);
+    let user_response = dialog.run_future().await;
+    dialog.close();
+
+    let known_hosts = client.known_hosts();
+    let mut known_hosts = known_hosts.lock().unwrap();
+    match user_response {
+        gtk::ResponseType::Apply => {
+            known_hosts.trust_once(&mismatch.host, &mismatch.presented);
+            true
+        }
+        gtk::ResponseType::Accept => {
+            if let Err(err) = known_hosts.trust_permanently(&mismatch.host, &mismatch.presented) {
+                eprintln!("Failed to save known hosts: {err}");
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+// Asks which identity to present to a capsule that requires a client
+// certificate, optionally creating or importing one first. Returns true if an
+// identity was bound and the load should be retried.
+async fn identity_modal(
+    client: &Client,
+    window: &ApplicationWindow,
+    url: &str,
+    meta: &str,
+) -> Result<bool> {
+    let dialog = Dialog::with_buttons(
+        Some("Client certificate required"),
+        Some(window),
+        DialogFlags::MODAL,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Use identity", gtk::ResponseType::Ok),
+        ],
+    );
+    let content = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    dialog.content_area().append(&content);
+
+    let message = if meta.is_empty() {
+        String::from("This page requires a client certificate")
+    } else {
+        meta.to_string()
+    };
+    content.append(&Label::new(Some(&message)));
+
+    let identity_choice = ComboBoxText::new();
+    for identity in client.identities().identities() {
+        identity_choice.append(Some(&identity.name), &identity.name);
+    }
+    identity_choice.append(Some("generate"), "Generate new identity");
+    identity_choice.append(Some("import"), "Import PEM certificate and key");
+    identity_choice.set_active(Some(0));
+    content.append(&identity_choice);
+
+    let name_entry = Entry::builder()
+        .placeholder_text("Name for the new identity")
+        .build();
+    content.append(&name_entry);
+    let update_name_entry = clone!(@weak name_entry => move |choice: &ComboBoxText| {
+        let id = choice.active_id();
+        name_entry.set_visible(matches!(id.as_deref(), Some("generate") | Some("import")));
+    });
+    update_name_entry(&identity_choice);
+    identity_choice.connect_changed(update_name_entry);
+
+    content.append(&Label::new(Some("Use this identity for this url and everything under it:")));
+    let scope_entry = Entry::builder().text(&identity::default_scope(url)?).build();
+    content.append(&scope_entry);
+
+    let user_response = dialog.run_future().await;
+    dialog.close();
+    if user_response != gtk::ResponseType::Ok {
+        return Ok(false);
+    }
+
+    let name = match identity_choice.active_id().as_deref() {
+        Some("generate") => {
+            let name = name_entry.text().to_string();
+            client.identities_mut().generate(&name)?;
+            name
+        }
+        Some("import") => {
+            let name = name_entry.text().to_string();
+            let certificate = match choose_file(window, "Select certificate").await {
+                Some(path) => path,
+                None => return Ok(false),
+            };
+            let key = match choose_file(window, "Select private key").await {
+                Some(path) => path,
+                None => return Ok(false),
+            };
+            client.identities_mut().import(&name, &certificate, &key)?;
+            name
+        }
+        Some(name) => name.to_string(),
+        None => return Ok(false),
+    };
+    client.identities_mut().bind(&scope_entry.text(), &name)?;
+    Ok(true)
+}
+
+async fn choose_file(window: &ApplicationWindow, title: &str) -> Option<PathBuf> {
+    let chooser = FileChooserDialog::new(
+        Some(title),
+        Some(window),
+        FileChooserAction::Open,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open", gtk::ResponseType::Accept),
+        ],
+    );
+    chooser.set_modal(true);
+    let user_response = chooser.run_future().await;
+    chooser.close();
+    if user_response == gtk::ResponseType::Accept {
+        chooser.file().and_then(|file| file.path())
+    } else {
+        None
+    }
+}
+
+// Lets the user add a bookmark for url, or edit and remove an existing one
+async fn bookmark_modal(
+    window: &ApplicationWindow,
+    bookmarks: &RefCell<Bookmarks>,
+    url: String,
+    title: String,
+) {
+    let existing = bookmarks.borrow().get(&url).cloned();
+    let dialog = Dialog::with_buttons(
+        Some(if existing.is_some() { "Edit bookmark" } else { "Add bookmark" }),
+        Some(window),
+        DialogFlags::MODAL,
+        &[("Cancel", gtk::ResponseType::Cancel)],
+    );
+    if existing.is_some() {
+        dialog.add_button("Remove", gtk::ResponseType::Reject);
+    }
+    dialog.add_button("Save", gtk::ResponseType::Ok);
+    dialog.set_default_response(gtk::ResponseType::Ok);
+
+    let grid = gtk::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    dialog.content_area().append(&grid);
+
+    let existing = existing.unwrap_or(Bookmark {
+        url: url.clone(),
+        title,
+        folder: String::new(),
+        tags: Vec::new(),
+    });
+    let title_entry = Entry::builder()
+        .text(&existing.title)
+        .hexpand(true)
+        .activates_default(true)
+        .build();
+    let folder_entry = ComboBoxText::with_entry();
+    for folder in bookmarks.borrow().folders() {
+        folder_entry.append_text(folder);
+    }
+    if let Some(entry) = folder_entry.child().and_then(|child| child.downcast::<Entry>().ok()) {
+        entry.set_text(&existing.folder);
+    }
+    let tags_entry = Entry::builder()
+        .text(&existing.tags.join(", "))
+        .placeholder_text("Comma separated tags")
+        .activates_default(true)
+        .build();
+
+    for (row, (label, widget)) in [
+        ("Title", title_entry.upcast_ref::<gtk::Widget>()),
+        ("Folder", folder_entry.upcast_ref()),
+        ("Tags", tags_entry.upcast_ref()),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        grid.attach(&Label::builder().label(label).xalign(0.0).build(), 0, row as i32, 1, 1);
+        grid.attach(widget, 1, row as i32, 1, 1);
+    }
+
+    let user_response = dialog.run_future().await;
+    dialog.close();
+    let result = match user_response {
+        gtk::ResponseType::Ok => bookmarks.borrow_mut().set(Bookmark {
+            url,
+            title: title_entry.text().to_string(),
+            folder: folder_entry
+                .active_text()
+                .map(|folder| folder.trim().to_string())
+                .unwrap_or_default(),
+            tags: crate::bookmarks::parse_tags(&tags_entry.text()),
+        }),
+        gtk::ResponseType::Reject => bookmarks.borrow_mut().remove(&url),
+        _ => return,
+    };
+    if let Err(err) = result {
+        load_page_error_modal(window, LoadPageError::Bookmarks(err)).await;
+    }
+}
+
+// Shows buffer in text_view with nothing but the image in it
+fn show_image(text_view: &TextView, buffer: &TextBuffer, texture: &gtk::gdk::Texture, url: &str) {
+    text_view.set_buffer(Some(buffer));
+    let picture = Picture::for_paintable(texture);
+    picture.set_can_shrink(false);
+    picture.set_tooltip_text(Some(url));
+    let anchor = TextChildAnchor::new();
+    buffer.insert_child_anchor(&mut buffer.end_iter(), &anchor);
+    text_view.add_child_at_anchor(&picture, &anchor);
+}
+
+// Shows a page loaded earlier again without fetching it. Returns false for
+// pages that can't be shown that way.
+fn show_cached_page(page: &Page, text_view: &TextView) -> bool {
+    let body = match &page.response.body {
+        Some(body) => body,
+        None => return false,
+    };
+    let buffer = TextBuffer::new(Some(&text_view.buffer().tag_table()));
+    let mime = Mime::parse(&page.response.header.meta);
+    if mime.is_text() && mime.has_known_charset() {
+        let mut writer = PageWriter::new(text_view, &buffer, mime, &page.url);
+        writer.write(body);
+        writer.finish();
+        true
+    } else if mime.is_image() {
+        match image_texture(body) {
+            Ok(texture) => {
+                show_image(text_view, &buffer, &texture, &page.url);
+                true
+            }
+            Err(_) => false,
+        }
+    } else {
+        false
+    }
+}
+
+fn image_texture(body: &[u8]) -> Result<gtk::gdk::Texture, glib::Error> {
+    let loader = gtk::gdk_pixbuf::PixbufLoader::new();
+    loader.write(body)?;
+    loader.close()?;
+    let pixbuf = loader
+        .pixbuf()
+        .ok_or_else(|| glib::Error::new(glib::FileError::Inval, "Image has no content"))?;
+    Ok(gtk::gdk::Texture::for_pixbuf(&pixbuf))
+}
+
+// Offers to save a response castor can't display. Returns Ok if the file was
+// saved or the user decided not to.
+async fn save_modal(window: &ApplicationWindow, url: &str, body: &[u8]) -> Result<()> {
+    match save_path_modal(window, url).await? {
+        Some(path) => std::fs::write(&path, body)
+            .with_context(|| format!("Failed to write {}", path.display())),
+        None => Ok(()),
+    }
+}
+
+// Like save_modal, for a download that's already on disk
+async fn save_spool_modal(window: &ApplicationWindow, url: &str, spool: Spool) -> Result<()> {
+    match save_path_modal(window, url).await? {
+        Some(path) => spool.save(&path),
+        None => Ok(()),
+    }
+}
+
+// Asks where to save what url responded with, None if the user would rather
+// not
+async fn save_path_modal(window: &ApplicationWindow, url: &str) -> Result<Option<PathBuf>> {
+    let chooser = FileChooserDialog::new(
+        Some("Save file"),
+        Some(window),
+        FileChooserAction::Save,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Save", gtk::ResponseType::Accept),
+        ],
+    );
+    chooser.set_modal(true);
+    let name = url::Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .and_then(|segments| segments.last().map(str::to_string))
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("download"));
+    chooser.set_current_name(&name);
+
+    let user_response = chooser.run_future().await;
+    chooser.close();
+    if user_response != gtk::ResponseType::Accept {
+        return Ok(None);
+    }
+    let path = chooser
+        .file()
+        .and_then(|file| file.path())
+        .context("No file selected")?;
+    Ok(Some(path))
+}
+
+// Asks whether to follow a redirect, optionally remembering the answer for
+// every redirect from host
+async fn redirect_modal(
+    window: &ApplicationWindow,
+    settings: &RefCell<Settings>,
+    code: header::RedirectCode,
+    host: &str,
+    target: &str,
+) -> bool {
+    let kind = match code {
+        header::RedirectCode::Temporary => "temporary",
+        header::RedirectCode::Permanent => "permanent",
+    };
+    let redirect_dialog = MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .buttons(ButtonsType::YesNo)
+        .text(&format!("This website has a {kind} redirect to {target}\nWould you like to continue?"))
+        .build();
+    let remember = gtk::CheckButton::with_label(&format!("Remember for {host}"));
+    redirect_dialog.content_area().append(&remember);
+    let user_response = redirect_dialog.run_future().await;
+    redirect_dialog.close();
+
+    let follow = matches!(user_response, gtk::ResponseType::Yes);
+    if remember.is_active() && !host.is_empty() {
+        let mut settings = settings.borrow_mut();
+        settings.redirect_rules.insert(host.to_string(), follow);
+        if let Err(err) = settings.save() {
+            eprintln!("Failed to save settings: {err:#}");
+        }
+    }
+    follow
+}
+
+// Asks whether to save a page too big to show, as soon as it gets that big.
+// The download carries on while the user thinks about it, saying no stops it.
+struct LargePagePrompt {
+    dialog: MessageDialog,
+    answer: Rc<Cell<Option<bool>>>,
+}
+
+impl LargePagePrompt {
+    fn show(window: &ApplicationWindow, max_size: usize, cancel: &Cancel) -> LargePagePrompt {
+        let dialog = MessageDialog::builder()
+            .transient_for(window)
+            .modal(true)
+            .buttons(ButtonsType::YesNo)
+            .text(&format!(
+                "This page is too large to show (over {:.1} MB)\nWould you like to save it instead?",
+                max_size as f64 / (1024.0 * 1024.0)
+            ))
+            .build();
+        let answer = Rc::new(Cell::new(None));
+        dialog.connect_response(
+            clone!(@strong answer, @strong cancel => move |dialog, response| {
+                let save = response == gtk::ResponseType::Yes;
+                answer.set(Some(save));
+                dialog.close();
+                if !save {
+                    cancel.cancel();
+                }
+            }),
+        );
+        dialog.show();
+        LargePagePrompt { dialog, answer }
+    }
+
+    // Whether the user wants the page saved, waiting for them to say if they
+    // haven't yet
+    async fn answer(self) -> bool {
+        if let Some(save) = self.answer.get() {
+            return save;
+        }
+        self.dialog.run_future().await == gtk::ResponseType::Yes
+    }
+
+    fn close(&self) {
+        self.dialog.close();
+    }
+}
+
 async fn load_page_error_modal(window: &ApplicationWindow, err: LoadPageError) {
     let error_dialog = MessageDialog::builder()
         .transient_for(window)
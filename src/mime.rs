@@ -0,0 +1,67 @@
+use std::borrow::Cow;
+
+use encoding_rs::Encoding;
+
+// The mime type sent in the meta string of a successful response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mime {
+    // type and subtype, lowercased, eg. "text/gemini"
+    pub essence: String,
+    params: Vec<(String, String)>,
+}
+
+impl Mime {
+    // An empty meta string means "text/gemini; charset=utf-8"
+    pub fn parse(meta: &str) -> Mime {
+        let mut parts = meta.split(';');
+        let essence = parts.next().unwrap_or_default().trim().to_lowercase();
+        if essence.is_empty() {
+            return Mime {
+                essence: String::from("text/gemini"),
+                params: Vec::new(),
+            };
+        }
+
+        let params = parts
+            .filter_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                Some((
+                    name.trim().to_lowercase(),
+                    value.trim().trim_matches('"').to_string(),
+                ))
+            })
+            .collect();
+        Mime { essence, params }
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.essence.starts_with("text/")
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.essence.starts_with("image/")
+    }
+
+    // Decodes a text body using the charset parameter, which defaults to
+    // utf-8. Returns None if the charset isn't one we know about.
+    pub fn decode<'a>(&self, body: &'a [u8]) -> Option<Cow<'a, str>> {
+        let (text, _, _) = self.encoding()?.decode(body);
+        Some(text)
+    }
+
+    pub fn has_known_charset(&self) -> bool {
+        self.encoding().is_some()
+    }
+
+    fn encoding(&self) -> Option<&'static Encoding> {
+        let charset = self.param("charset").unwrap_or("utf-8");
+        Encoding::for_label(charset.as_bytes())
+    }
+}
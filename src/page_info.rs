@@ -0,0 +1,73 @@
+use std::rc::Rc;
+
+use gtk::{prelude::*, Grid, Label, Window};
+use gtk4 as gtk;
+
+use crate::browser::Browser;
+use crate::tab::Tab;
+
+// Opens a window with what castor knows about the page tab is showing: the
+// response header, how it got there and who it was talking to.
+pub fn show(browser: &Rc<Browser>, tab: &Tab) {
+    let page = tab.page.borrow();
+    let page = match &*page {
+        Some(page) => page,
+        None => return,
+    };
+
+    let window = Window::builder()
+        .title("Page info")
+        .transient_for(&browser.window)
+        .default_width(500)
+        .build();
+    let grid = Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    window.set_child(Some(&grid));
+
+    let response = &page.response;
+    let size = response.body.as_ref().map_or(0, Vec::len);
+    let redirects = if page.redirects.is_empty() {
+        String::from("none")
+    } else {
+        page.redirects.join("\n→ ") + "\n→ " + &page.url
+    };
+    let mut rows = vec![
+        ("Address", page.url.clone()),
+        ("Status", response.header.status.to_string()),
+        ("Meta", response.header.meta.clone()),
+        ("Size", format!("{size} bytes")),
+        ("Redirects", redirects),
+    ];
+    match &response.certificate {
+        Some(certificate) => rows.extend([
+            ("Certificate subject", certificate.subject.clone()),
+            ("Certificate issuer", certificate.issuer.clone()),
+            ("Valid from", certificate.not_before.clone()),
+            ("Valid until", certificate.not_after.clone()),
+            ("SHA-256 fingerprint", certificate.fingerprint.clone()),
+        ]),
+        None => rows.push(("Certificate", String::from("none"))),
+    }
+
+    for (row, (name, value)) in rows.into_iter().enumerate() {
+        let name = Label::builder().label(name).xalign(0.0).yalign(0.0).build();
+        // selectable so fingerprints and urls can be copied out
+        let value = Label::builder()
+            .label(&value)
+            .xalign(0.0)
+            .wrap(true)
+            .wrap_mode(gtk::pango::WrapMode::WordChar)
+            .selectable(true)
+            .build();
+        grid.attach(&name, 0, row as i32, 1, 1);
+        grid.attach(&value, 1, row as i32, 1, 1);
+    }
+
+    window.present();
+}
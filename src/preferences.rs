@@ -0,0 +1,212 @@
+use std::rc::Rc;
+
+use glib::clone;
+use gtk::{
+    gdk, prelude::*, Button, ColorButton, ComboBoxText, Entry, FontButton, Grid, Label,
+    SpinButton, Window,
+};
+use gtk4 as gtk;
+
+use crate::browser::Browser;
+use crate::settings::{RedirectPolicy, Settings, Startup};
+use crate::shortcuts::{self, SHORTCUTS};
+
+// Opens the preferences window, every change is saved and applied right away
+pub fn show(browser: &Rc<Browser>) {
+    let window = Window::builder()
+        .title("Preferences")
+        .transient_for(&browser.window)
+        .resizable(false)
+        .build();
+    let grid = Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    window.set_child(Some(&grid));
+
+    let settings = browser.profile.settings.borrow().clone();
+
+    let home_page = Entry::builder()
+        .text(&settings.home_page)
+        .hexpand(true)
+        .build();
+    let use_current = Button::with_label("Use current page");
+    let home_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    home_box.append(&home_page);
+    home_box.append(&use_current);
+
+    let startup = ComboBoxText::new();
+    startup.append(Some(Startup::Home.id()), "Show the home page");
+    startup.append(Some(Startup::Blank.id()), "Show a blank page");
+    startup.append(Some(Startup::RestoreSession.id()), "Reopen the last session's tabs");
+    startup.set_active_id(Some(settings.startup.id()));
+
+    let redirect_policy = ComboBoxText::new();
+    redirect_policy.append(Some(RedirectPolicy::Ask.id()), "Always ask");
+    redirect_policy.append(
+        Some(RedirectPolicy::SameHost.id()),
+        "Follow redirects to the same capsule",
+    );
+    redirect_policy.append(Some(RedirectPolicy::Always.id()), "Always follow");
+    redirect_policy.set_active_id(Some(settings.redirect_policy.id()));
+
+    let forget_redirects = Button::with_label("Forget remembered redirect choices");
+    forget_redirects.set_sensitive(!settings.redirect_rules.is_empty());
+    let redirect_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    redirect_box.append(&redirect_policy);
+    redirect_box.append(&forget_redirects);
+
+    let request_timeout = SpinButton::with_range(1.0, 600.0, 1.0);
+    request_timeout.set_value(settings.request_timeout as f64);
+    let max_page_size = SpinButton::with_range(1.0, 1000.0, 1.0);
+    max_page_size.set_value(settings.max_page_size as f64);
+
+    let cache_size = SpinButton::with_range(0.0, 1000.0, 1.0);
+    cache_size.set_value(settings.cache_size as f64);
+    let clear_cache = Button::with_label("Clear cache");
+    let cache_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    cache_box.append(&cache_size);
+    cache_box.append(&clear_cache);
+
+    let font = FontButton::with_font(&settings.font);
+    let monospace_font = FontButton::with_font(&settings.monospace_font);
+    let link_color = ColorButton::new();
+    if let Ok(color) = gdk::RGBA::parse(&settings.link_color) {
+        link_color.set_rgba(&color);
+    }
+    let visited_link_color = ColorButton::new();
+    if let Ok(color) = gdk::RGBA::parse(&settings.visited_link_color) {
+        visited_link_color.set_rgba(&color);
+    }
+
+    // shortcuts use gtk's accelerator syntax, eg. "<Control><Shift>t"
+    let shortcut_grid = Grid::builder().row_spacing(6).column_spacing(12).build();
+    let shortcut_entries: Vec<_> = SHORTCUTS
+        .iter()
+        .enumerate()
+        .map(|(row, (action, description, _))| {
+            let entry = Entry::builder()
+                .text(shortcuts::accel(&settings, action))
+                .placeholder_text("No shortcut")
+                .build();
+            shortcut_grid.attach(
+                &Label::builder().label(*description).xalign(0.0).build(),
+                0,
+                row as i32,
+                1,
+                1,
+            );
+            shortcut_grid.attach(&entry, 1, row as i32, 1, 1);
+            (*action, entry)
+        })
+        .collect();
+
+    for (row, (label, widget)) in [
+        ("Home page", home_box.upcast_ref::<gtk::Widget>()),
+        ("On startup", startup.upcast_ref()),
+        ("Redirects", redirect_box.upcast_ref()),
+        ("Request timeout (seconds)", request_timeout.upcast_ref()),
+        ("Largest page to show (MB)", max_page_size.upcast_ref()),
+        ("Page cache (MB)", cache_box.upcast_ref()),
+        ("Font", font.upcast_ref()),
+        ("Preformatted font", monospace_font.upcast_ref()),
+        ("Link colour", link_color.upcast_ref()),
+        ("Visited link colour", visited_link_color.upcast_ref()),
+        ("Shortcuts", shortcut_grid.upcast_ref()),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        grid.attach(&Label::builder().label(label).xalign(0.0).build(), 0, row as i32, 1, 1);
+        grid.attach(widget, 1, row as i32, 1, 1);
+    }
+
+    let weak = Rc::downgrade(browser);
+    let update = Rc::new(clone!(@strong weak => move |change: &dyn Fn(&mut Settings)| {
+        if let Some(browser) = weak.upgrade() {
+            browser.update_settings(change);
+        }
+    }));
+
+    home_page.connect_changed(clone!(@strong update => move |entry| {
+        let url = entry.text().to_string();
+        update(&move |settings: &mut Settings| settings.home_page = url.clone());
+    }));
+    use_current.connect_clicked(clone!(@strong weak, @weak home_page => move |_| {
+        if let Some(tab) = weak.upgrade().and_then(|browser| browser.current_tab()) {
+            home_page.set_text(&tab.state.borrow().current_url());
+        }
+    }));
+    startup.connect_changed(clone!(@strong update => move |combo| {
+        if let Some(startup) = combo.active_id().and_then(|id| Startup::from_id(&id)) {
+            update(&move |settings: &mut Settings| settings.startup = startup);
+        }
+    }));
+    redirect_policy.connect_changed(clone!(@strong update => move |combo| {
+        if let Some(policy) = combo.active_id().and_then(|id| RedirectPolicy::from_id(&id)) {
+            update(&move |settings: &mut Settings| settings.redirect_policy = policy);
+        }
+    }));
+    forget_redirects.connect_clicked(clone!(@strong update => move |button| {
+        update(&|settings: &mut Settings| settings.redirect_rules.clear());
+        button.set_sensitive(false);
+    }));
+    request_timeout.connect_value_changed(clone!(@strong update => move |spin| {
+        let timeout = spin.value_as_int() as u64;
+        update(&move |settings: &mut Settings| settings.request_timeout = timeout);
+    }));
+    max_page_size.connect_value_changed(clone!(@strong update => move |spin| {
+        let size = spin.value_as_int() as u64;
+        update(&move |settings: &mut Settings| settings.max_page_size = size);
+    }));
+    cache_size.connect_value_changed(clone!(@strong update => move |spin| {
+        let size = spin.value_as_int() as u64;
+        update(&move |settings: &mut Settings| settings.cache_size = size);
+    }));
+    clear_cache.connect_clicked(clone!(@strong weak => move |_| {
+        if let Some(browser) = weak.upgrade() {
+            browser.clear_cache();
+        }
+    }));
+    font.connect_font_set(clone!(@strong update => move |button| {
+        if let Some(font) = button.font() {
+            update(&move |settings: &mut Settings| settings.font = font.to_string());
+        }
+    }));
+    monospace_font.connect_font_set(clone!(@strong update => move |button| {
+        if let Some(font) = button.font() {
+            update(&move |settings: &mut Settings| settings.monospace_font = font.to_string());
+        }
+    }));
+    link_color.connect_color_set(clone!(@strong update => move |button| {
+        let color = button.rgba().to_string();
+        update(&move |settings: &mut Settings| settings.link_color = color.clone());
+    }));
+    visited_link_color.connect_color_set(clone!(@strong update => move |button| {
+        let color = button.rgba().to_string();
+        update(&move |settings: &mut Settings| settings.visited_link_color = color.clone());
+    }));
+
+    for (action, entry) in shortcut_entries {
+        entry.connect_changed(clone!(@strong update => move |entry| {
+            let accel = entry.text().trim().to_string();
+            if !shortcuts::is_valid(&accel) {
+                entry.add_css_class("error");
+                return;
+            }
+            entry.remove_css_class("error");
+            update(&move |settings: &mut Settings| {
+                settings.shortcuts.remove(action);
+                if shortcuts::accel(settings, action) != accel {
+                    settings.shortcuts.insert(action.to_string(), accel.clone());
+                }
+            });
+        }));
+    }
+
+    window.present();
+}
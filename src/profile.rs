@@ -0,0 +1,52 @@
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use gtk::TextTagTable;
+use gtk4 as gtk;
+
+use crate::bookmarks::Bookmarks;
+use crate::cache::Cache;
+use crate::client::Client;
+use crate::history::History;
+use crate::settings::Settings;
+use crate::{apply_settings, text_tags, Page};
+
+// Everything every window shares, loaded once when castor starts. The stores
+// rewrite their files from what they hold, so windows with copies of their
+// own would undo each other's changes.
+pub struct Profile {
+    pub client: Client,
+    pub bookmarks: RefCell<Bookmarks>,
+    pub history: RefCell<History>,
+    pub settings: RefCell<Settings>,
+    // pages recently loaded in any tab
+    pub cache: RefCell<Cache<Page>>,
+    // shared by every page, so changes to the settings show everywhere
+    pub tag_table: TextTagTable,
+    // the last window to close is the one that saves the session
+    pub open_windows: Cell<usize>,
+}
+
+impl Profile {
+    pub fn load() -> Result<Profile> {
+        let settings = Settings::load().context("Failed to load settings")?;
+        let tag_table = text_tags();
+        apply_settings(&tag_table, &settings);
+        let client = Client::new().context("Failed to create gemini client")?;
+        client.set_timeout(Duration::from_secs(settings.request_timeout));
+        Ok(Profile {
+            client,
+            bookmarks: RefCell::new(Bookmarks::load().context("Failed to load bookmarks")?),
+            history: RefCell::new(History::load().context("Failed to load history")?),
+            cache: RefCell::new(Cache::new(cache_capacity(&settings))),
+            settings: RefCell::new(settings),
+            tag_table,
+            open_windows: Cell::new(0),
+        })
+    }
+}
+
+pub fn cache_capacity(settings: &Settings) -> usize {
+    settings.cache_size as usize * 1024 * 1024
+}
@@ -0,0 +1,179 @@
+// A page in a tab's back/forward history
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    // the url the page was finally loaded from, after redirects and input
+    pub url: String,
+    pub title: Option<String>,
+    // vertical scroll offset to restore when coming back to the page
+    pub scroll: f64,
+}
+
+impl HistoryEntry {
+    fn new(url: String, title: Option<String>) -> HistoryEntry {
+        HistoryEntry {
+            url,
+            title,
+            scroll: 0.0,
+        }
+    }
+}
+
+// Back/forward history of a single tab. There is always a current entry, the
+// entries before it can be gone back to and the ones after it forward to.
+#[derive(Debug, Clone)]
+pub struct SessionHistory {
+    entries: Vec<HistoryEntry>,
+    index: usize,
+}
+
+impl SessionHistory {
+    pub fn new(url: String) -> SessionHistory {
+        SessionHistory {
+            entries: vec![HistoryEntry::new(url, None)],
+            index: 0,
+        }
+    }
+
+    pub fn current(&self) -> &HistoryEntry {
+        &self.entries[self.index]
+    }
+
+    pub fn current_mut(&mut self) -> &mut HistoryEntry {
+        &mut self.entries[self.index]
+    }
+
+    // Makes url the current entry, dropping everything that could have been
+    // gone forward to. Loading the current url again only updates it.
+    pub fn push(&mut self, url: String, title: Option<String>) {
+        if self.current().url == url {
+            self.replace(url, title);
+            return;
+        }
+        self.entries.truncate(self.index + 1);
+        self.entries.push(HistoryEntry::new(url, title));
+        self.index += 1;
+    }
+
+    // Updates the current entry in place, keeping its scroll position
+    pub fn replace(&mut self, url: String, title: Option<String>) {
+        let current = self.current_mut();
+        current.url = url;
+        current.title = title;
+    }
+
+    pub fn peek_back(&self) -> Option<&HistoryEntry> {
+        self.index.checked_sub(1).map(|index| &self.entries[index])
+    }
+
+    pub fn peek_forward(&self) -> Option<&HistoryEntry> {
+        self.entries.get(self.index + 1)
+    }
+
+    pub fn back(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_go_back() {
+            return None;
+        }
+        self.index -= 1;
+        Some(self.current())
+    }
+
+    pub fn forward(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_go_forward() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.current())
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.index > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.index + 1 < self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(history: &SessionHistory) -> Vec<&str> {
+        history.entries.iter().map(|entry| entry.url.as_str()).collect()
+    }
+
+    #[test]
+    fn starts_with_single_entry() {
+        let history = SessionHistory::new(String::from("gemini://a/"));
+        assert_eq!(history.current().url, "gemini://a/");
+        assert!(!history.can_go_back());
+        assert!(!history.can_go_forward());
+        assert!(history.peek_back().is_none());
+        assert!(history.peek_forward().is_none());
+    }
+
+    #[test]
+    fn push_then_back_and_forward() {
+        let mut history = SessionHistory::new(String::from("gemini://a/"));
+        history.push(String::from("gemini://b/"), None);
+        history.push(String::from("gemini://c/"), Some(String::from("C")));
+        assert_eq!(history.current().title.as_deref(), Some("C"));
+
+        assert_eq!(history.back().unwrap().url, "gemini://b/");
+        assert_eq!(history.back().unwrap().url, "gemini://a/");
+        assert!(history.back().is_none());
+        assert_eq!(history.current().url, "gemini://a/");
+
+        assert_eq!(history.forward().unwrap().url, "gemini://b/");
+        assert_eq!(history.forward().unwrap().url, "gemini://c/");
+        assert!(history.forward().is_none());
+        assert_eq!(history.current().url, "gemini://c/");
+    }
+
+    #[test]
+    fn push_drops_forward_entries() {
+        let mut history = SessionHistory::new(String::from("gemini://a/"));
+        history.push(String::from("gemini://b/"), None);
+        history.push(String::from("gemini://c/"), None);
+        history.back();
+        history.back();
+        history.push(String::from("gemini://d/"), None);
+
+        assert_eq!(urls(&history), ["gemini://a/", "gemini://d/"]);
+        assert!(!history.can_go_forward());
+        assert_eq!(history.peek_back().unwrap().url, "gemini://a/");
+    }
+
+    #[test]
+    fn push_of_current_url_replaces() {
+        let mut history = SessionHistory::new(String::from("gemini://a/"));
+        history.current_mut().scroll = 42.0;
+        history.push(String::from("gemini://a/"), Some(String::from("A")));
+
+        assert_eq!(urls(&history), ["gemini://a/"]);
+        assert_eq!(history.current().title.as_deref(), Some("A"));
+        assert_eq!(history.current().scroll, 42.0);
+    }
+
+    #[test]
+    fn replace_keeps_position_and_scroll() {
+        let mut history = SessionHistory::new(String::from("gemini://a/"));
+        history.push(String::from("gemini://b/"), None);
+        history.current_mut().scroll = 10.0;
+        history.replace(String::from("gemini://b/redirected"), None);
+
+        assert_eq!(urls(&history), ["gemini://a/", "gemini://b/redirected"]);
+        assert_eq!(history.current().scroll, 10.0);
+    }
+
+    #[test]
+    fn scroll_is_kept_per_entry() {
+        let mut history = SessionHistory::new(String::from("gemini://a/"));
+        history.current_mut().scroll = 100.0;
+        history.push(String::from("gemini://b/"), None);
+        history.current_mut().scroll = 5.0;
+
+        assert_eq!(history.back().unwrap().scroll, 100.0);
+        assert_eq!(history.forward().unwrap().scroll, 5.0);
+    }
+}
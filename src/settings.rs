@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+// What castor shows when it starts without a url on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Startup {
+    Home,
+    Blank,
+    RestoreSession,
+}
+
+// What to do when a capsule redirects somewhere else
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    Ask,
+    // follow redirects to the same host without asking, ask about the rest
+    SameHost,
+    Always,
+}
+
+impl Startup {
+    pub fn id(&self) -> &'static str {
+        match self {
+            Startup::Home => "home",
+            Startup::Blank => "blank",
+            Startup::RestoreSession => "restore",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Startup> {
+        match id {
+            "home" => Some(Startup::Home),
+            "blank" => Some(Startup::Blank),
+            "restore" => Some(Startup::RestoreSession),
+            _ => None,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    pub fn id(&self) -> &'static str {
+        match self {
+            RedirectPolicy::Ask => "ask",
+            RedirectPolicy::SameHost => "same-host",
+            RedirectPolicy::Always => "always",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<RedirectPolicy> {
+        match id {
+            "ask" => Some(RedirectPolicy::Ask),
+            "same-host" => Some(RedirectPolicy::SameHost),
+            "always" => Some(RedirectPolicy::Always),
+            _ => None,
+        }
+    }
+}
+
+// User preferences, kept in the castor config directory as "key = value"
+// lines. Unknown keys and bad values are ignored in favour of the defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub home_page: String,
+    pub startup: Startup,
+    pub redirect_policy: RedirectPolicy,
+    // per capsule answers to the redirect prompt the user asked us to
+    // remember, true to follow. Saved as "redirect.<host> = follow|block".
+    pub redirect_rules: BTreeMap<String, bool>,
+    // seconds a request may take before it's given up on
+    pub request_timeout: u64,
+    // megabytes of text a page may have before it's offered as a download
+    // rather than shown
+    pub max_page_size: u64,
+    // megabytes of recently visited pages kept for going back and forward
+    pub cache_size: u64,
+    // pango font descriptions, eg. "Sans 11"
+    pub font: String,
+    pub monospace_font: String,
+    // anything gtk can parse as a colour, eg. "blue" or "#3465a4"
+    pub link_color: String,
+    pub visited_link_color: String,
+    // keyboard shortcuts that differ from the defaults, by action name. Saved
+    // as "shortcut.<action> = <accelerator>", empty for no shortcut.
+    pub shortcuts: BTreeMap<String, String>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            home_page: String::from(crate::DEFAULT_URL),
+            startup: Startup::Home,
+            redirect_policy: RedirectPolicy::Ask,
+            redirect_rules: BTreeMap::new(),
+            request_timeout: 30,
+            max_page_size: 10,
+            cache_size: 20,
+            font: String::from("Sans 11"),
+            monospace_font: String::from("Monospace 11"),
+            link_color: String::from("blue"),
+            visited_link_color: String::from("purple"),
+            shortcuts: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> PathBuf {
+        crate::config_dir().join("settings")
+    }
+
+    pub fn load() -> Result<Settings> {
+        let path = Settings::path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let mut settings = Settings::default();
+        for line in contents.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            match key {
+                "home_page" => settings.home_page = value.to_string(),
+                "startup" => {
+                    if let Some(startup) = Startup::from_id(value) {
+                        settings.startup = startup;
+                    }
+                }
+                "redirect_policy" => {
+                    if let Some(policy) = RedirectPolicy::from_id(value) {
+                        settings.redirect_policy = policy;
+                    }
+                }
+                "request_timeout" => {
+                    if let Ok(timeout) = value.parse() {
+                        settings.request_timeout = timeout;
+                    }
+                }
+                "max_page_size" => {
+                    if let Ok(size) = value.parse() {
+                        settings.max_page_size = size;
+                    }
+                }
+                "cache_size" => {
+                    if let Ok(size) = value.parse() {
+                        settings.cache_size = size;
+                    }
+                }
+                "font" => settings.font = value.to_string(),
+                "monospace_font" => settings.monospace_font = value.to_string(),
+                "link_color" => settings.link_color = value.to_string(),
+                "visited_link_color" => settings.visited_link_color = value.to_string(),
+                _ => {
+                    if let Some(host) = key.strip_prefix("redirect.") {
+                        match value {
+                            "follow" => settings.redirect_rules.insert(host.to_string(), true),
+                            "block" => settings.redirect_rules.insert(host.to_string(), false),
+                            _ => None,
+                        };
+                    } else if let Some(action) = key.strip_prefix("shortcut.") {
+                        settings.shortcuts.insert(action.to_string(), value.to_string());
+                    }
+                }
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Settings::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        let mut contents = format!(
+            "home_page = {}\nstartup = {}\nredirect_policy = {}\nrequest_timeout = {}\nmax_page_size = {}\ncache_size = {}\nfont = {}\nmonospace_font = {}\nlink_color = {}\nvisited_link_color = {}\n",
+            self.home_page,
+            self.startup.id(),
+            self.redirect_policy.id(),
+            self.request_timeout,
+            self.max_page_size,
+            self.cache_size,
+            self.font,
+            self.monospace_font,
+            self.link_color,
+            self.visited_link_color,
+        );
+        for (host, follow) in &self.redirect_rules {
+            let rule = if *follow { "follow" } else { "block" };
+            contents += &format!("redirect.{host} = {rule}\n");
+        }
+        for (action, accel) in &self.shortcuts {
+            contents += &format!("shortcut.{action} = {accel}\n");
+        }
+        fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+// Urls of the tabs that were open when castor last exited, one per line in
+// the castor data directory
+pub fn load_session() -> Vec<String> {
+    fs::read_to_string(crate::data_dir().join("session"))
+        .map(|contents| {
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn save_session(urls: &[String]) -> Result<()> {
+    let path = crate::data_dir().join("session");
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let contents: String = urls.iter().map(|url| format!("{url}\n")).collect();
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,50 @@
+use gtk::{prelude::*, Application};
+use gtk4 as gtk;
+
+use crate::settings::Settings;
+
+// Every action that can have a keyboard shortcut: its name, what it does and
+// the shortcut it has unless the settings say otherwise
+pub const SHORTCUTS: &[(&str, &str, &str)] = &[
+    ("win.back", "Go back", "<Alt>Left"),
+    ("win.forward", "Go forward", "<Alt>Right"),
+    ("win.refresh", "Reload the page", "F5"),
+    // escape is left to the find bar and link numbers
+    ("win.stop", "Stop loading", ""),
+    ("win.focus-url", "Edit the address", "<Control>l"),
+    ("win.new-tab", "Open a new tab", "<Control>t"),
+    ("win.close-tab", "Close the tab", "<Control>w"),
+    ("win.reopen-closed-tab", "Reopen a closed tab", "<Control><Shift>t"),
+    ("win.find", "Find in page", "<Control>f"),
+    ("win.link-numbers", "Follow a link by number", "<Alt>n"),
+    ("win.clear-cache", "Clear the page cache", ""),
+];
+
+// The shortcut for action, an empty string means it has none
+pub fn accel<'a>(settings: &'a Settings, action: &str) -> &'a str {
+    match settings.shortcuts.get(action) {
+        Some(accel) => accel,
+        None => SHORTCUTS
+            .iter()
+            .find(|(name, _, _)| *name == action)
+            .map_or("", |(_, _, default)| default),
+    }
+}
+
+pub fn is_valid(accel: &str) -> bool {
+    accel.is_empty() || gtk::accelerator_parse(accel).is_some()
+}
+
+// Registers the shortcuts on app, which makes them work in all its windows
+pub fn apply(app: &Application, settings: &Settings) {
+    for (action, _, _) in SHORTCUTS {
+        // bad shortcuts in the settings file are dropped rather than
+        // making gtk complain
+        let accel = accel(settings, action);
+        if accel.is_empty() || !is_valid(accel) {
+            app.set_accels_for_action(action, &[]);
+        } else {
+            app.set_accels_for_action(action, &[accel]);
+        }
+    }
+}
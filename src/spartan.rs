@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use percent_encoding::percent_decode_str;
+
+use crate::client::{self, Error, Progress, Response};
+use crate::header::{FailPermanentCode, FailTemporaryCode, Header, RedirectCode, StatusCode};
+
+const DEFAULT_PORT: u16 = 300;
+
+// Fetches a spartan url. Any query is sent as the request's data, which is
+// how the input asked for by prompt lines gets to the server.
+pub async fn request(
+    url: &url::Url,
+    timeout: Duration,
+    progress: &dyn Fn(Progress),
+    body: &dyn Fn(&Header, &[u8]),
+) -> Result<Response, Error> {
+    let host = url.host_str().ok_or(Error::MissingHost)?;
+    let path = if url.path().is_empty() { "/" } else { url.path() };
+    let data: Vec<u8> = url
+        .query()
+        .map(|query| percent_decode_str(query).collect())
+        .unwrap_or_default();
+
+    let mut request = format!("{host} {path} {}\r\n", data.len()).into_bytes();
+    request.extend_from_slice(&data);
+    client::plain_exchange(
+        host,
+        url.port().unwrap_or(DEFAULT_PORT),
+        &request,
+        &|data| client::parse_header_with(data, status),
+        timeout,
+        progress,
+        body,
+    )
+    .await
+}
+
+// Spartan has a single digit for each kind of response, redirects are to a
+// path on the same host
+fn status(code: &str) -> Option<StatusCode> {
+    match code {
+        "2" => Some(StatusCode::Success),
+        "3" => Some(StatusCode::Redirect(RedirectCode::Temporary)),
+        "4" => Some(StatusCode::FailPermanent(FailPermanentCode::BadRequest)),
+        "5" => Some(StatusCode::FailTemporary(FailTemporaryCode::TemporaryFailure)),
+        _ => None,
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result};
+
+// A download too big to keep in memory, written to a temporary file as it
+// arrives until it's saved somewhere. The file goes away if it never is.
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+}
+
+impl Spool {
+    pub fn create() -> Result<Spool> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "castor-{}-{}.part",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        Spool::create_in(&std::env::temp_dir(), &name)
+    }
+
+    fn create_in(dir: &Path, name: &str) -> Result<Spool> {
+        let path = dir.join(name);
+        // only the user gets to see what they're downloading
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Spool { path, file })
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.file
+            .write_all(data)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    // Moves the download to path
+    pub fn save(self, path: &Path) -> Result<()> {
+        // renaming doesn't work across filesystems, copying does
+        if fs::rename(&self.path, path).is_err() {
+            fs::copy(&self.path, path)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("castor-spool-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn save_moves_what_was_written() {
+        let dir = test_dir("save");
+        let mut spool = Spool::create_in(&dir, "page.part").unwrap();
+        spool.write(b"# Big ").unwrap();
+        spool.write(b"page\n").unwrap();
+        spool.save(&dir.join("page.gmi")).unwrap();
+        assert_eq!(fs::read(dir.join("page.gmi")).unwrap(), b"# Big page\n");
+        assert!(!dir.join("page.part").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dropping_removes_the_file() {
+        let dir = test_dir("drop");
+        let mut spool = Spool::create_in(&dir, "page.part").unwrap();
+        spool.write(b"never saved").unwrap();
+        drop(spool);
+        assert!(!dir.join("page.part").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+// Gemtext parsing a line at a time, so pages can be shown while they're still
+// arriving. Bytes go in as they come off the network and complete lines come
+// out, a line cut off by the end of a chunk waits for the rest of it.
+
+// A parsed line of gemtext
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Text(String),
+    // url, then the text to show for it which may be empty
+    Link(String, String),
+    // a spartan link that asks for input to send to the url, like Link
+    Prompt(String, String),
+    Heading(String),
+    Subheading(String),
+    Subsubheading(String),
+    ListItem(String),
+    Quote(String),
+    // the opening ``` of a preformatted block, with its alt text
+    PreformattedStart(String),
+    // a line inside a preformatted block, with the block's alt text
+    Preformatted(String, String),
+    PreformattedEnd,
+}
+
+// Splits bytes into lines, without their line endings
+#[derive(Default)]
+pub struct LineSplitter {
+    partial: Vec<u8>,
+}
+
+impl LineSplitter {
+    // The lines completed by chunk
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|byte| *byte == b'\n') {
+            self.partial.extend_from_slice(&rest[..end]);
+            lines.push(trim_cr(std::mem::take(&mut self.partial)));
+            rest = &rest[end + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+        lines
+    }
+
+    // The last line, if the text didn't end with a line break
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        if self.partial.is_empty() {
+            None
+        } else {
+            Some(trim_cr(std::mem::take(&mut self.partial)))
+        }
+    }
+}
+
+fn trim_cr(mut line: Vec<u8>) -> Vec<u8> {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    line
+}
+
+// Parses lines one after another, remembering whether they're inside a
+// preformatted block
+#[derive(Default)]
+pub struct Parser {
+    // the alt text of the preformatted block we're in, if any
+    preformatted: Option<String>,
+    // whether "=:" lines are prompts, they're only a thing on spartan
+    prompts: bool,
+}
+
+impl Parser {
+    pub fn spartan() -> Parser {
+        Parser {
+            prompts: true,
+            ..Parser::default()
+        }
+    }
+
+    // The parser for a page from url, only spartan pages have prompts
+    pub fn for_url(url: &str) -> Parser {
+        if url.starts_with("spartan://") {
+            Parser::spartan()
+        } else {
+            Parser::default()
+        }
+    }
+
+    pub fn parse(&mut self, line: &str) -> Line {
+        if let Some(alt_text) = line.strip_prefix("```") {
+            return match self.preformatted.take() {
+                Some(_) => Line::PreformattedEnd,
+                None => {
+                    let alt_text = alt_text.trim().to_string();
+                    self.preformatted = Some(alt_text.clone());
+                    Line::PreformattedStart(alt_text)
+                }
+            };
+        }
+        if let Some(alt_text) = &self.preformatted {
+            return Line::Preformatted(alt_text.clone(), line.to_string());
+        }
+
+        if let Some(link) = line.strip_prefix("=>") {
+            let (url, text) = split_link(link);
+            Line::Link(url, text)
+        } else if let Some(prompt) = line.strip_prefix("=:").filter(|_| self.prompts) {
+            let (url, text) = split_link(prompt);
+            Line::Prompt(url, text)
+        } else if let Some(text) = line.strip_prefix("###") {
+            Line::Subsubheading(text.trim_start().to_string())
+        } else if let Some(text) = line.strip_prefix("##") {
+            Line::Subheading(text.trim_start().to_string())
+        } else if let Some(text) = line.strip_prefix('#') {
+            Line::Heading(text.trim_start().to_string())
+        } else if let Some(text) = line.strip_prefix("* ") {
+            Line::ListItem(text.trim_start().to_string())
+        } else if let Some(text) = line.strip_prefix('>') {
+            Line::Quote(text.trim_start().to_string())
+        } else {
+            Line::Text(line.to_string())
+        }
+    }
+}
+
+// The url and text of what follows a link's "=>"
+fn split_link(link: &str) -> (String, String) {
+    let link = link.trim();
+    let (url, text) = link
+        .split_once(char::is_whitespace)
+        .unwrap_or((link, ""));
+    (url.to_string(), text.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<Line> {
+        let mut parser = Parser::default();
+        text.lines().map(|line| parser.parse(line)).collect()
+    }
+
+    #[test]
+    fn lines_can_span_chunks() {
+        let mut splitter = LineSplitter::default();
+        assert_eq!(splitter.push(b"# Tit"), Vec::<Vec<u8>>::new());
+        assert_eq!(splitter.push(b"le\r\nsome "), vec![b"# Title".to_vec()]);
+        assert_eq!(splitter.push(b"text\n\n"), vec![b"some text".to_vec(), Vec::new()]);
+        assert_eq!(splitter.push(b"end"), Vec::<Vec<u8>>::new());
+        assert_eq!(splitter.finish(), Some(b"end".to_vec()));
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn parses_line_types() {
+        assert_eq!(
+            parse("# One\n## Two\n### Three\n* item\n> quote\n=> gemini://a.b/ A link\n=>/c\nplain"),
+            vec![
+                Line::Heading(String::from("One")),
+                Line::Subheading(String::from("Two")),
+                Line::Subsubheading(String::from("Three")),
+                Line::ListItem(String::from("item")),
+                Line::Quote(String::from("quote")),
+                Line::Link(String::from("gemini://a.b/"), String::from("A link")),
+                Line::Link(String::from("/c"), String::new()),
+                Line::Text(String::from("plain")),
+            ]
+        );
+    }
+
+    #[test]
+    fn prompts_are_only_for_spartan() {
+        let line = "=: /guestbook Sign the guestbook";
+        assert_eq!(
+            Parser::spartan().parse(line),
+            Line::Prompt(String::from("/guestbook"), String::from("Sign the guestbook"))
+        );
+        assert_eq!(Parser::default().parse(line), Line::Text(String::from(line)));
+    }
+
+    #[test]
+    fn preformatted_blocks_keep_their_lines() {
+        assert_eq!(
+            parse("```art\n# not a heading\n```\n# heading"),
+            vec![
+                Line::PreformattedStart(String::from("art")),
+                Line::Preformatted(String::from("art"), String::from("# not a heading")),
+                Line::PreformattedEnd,
+                Line::Heading(String::from("heading")),
+            ]
+        );
+    }
+}
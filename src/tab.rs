@@ -0,0 +1,536 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use glib::{clone, MainContext, Receiver, Sender, PRIORITY_DEFAULT};
+use gtk::{
+    gdk, gio, pango, prelude::*, Button, EventControllerKey, EventControllerMotion, GestureClick,
+    Label, Orientation, Popover, ScrolledWindow, TextBuffer, TextIter, TextTagTable, TextView,
+};
+use gtk4 as gtk;
+
+use crate::cancel::Cancel;
+use crate::history::History;
+use crate::mime::Mime;
+use crate::{resolve_url, Castor, Page, ALT_TEXT_MARK, ALT_TEXT_TAG, HREF_MARK, PROMPT_TAG};
+
+// What clicking on, or hovering over, a link asks the browser to do with its
+// url
+#[derive(Clone)]
+pub enum LinkAction {
+    Follow(String),
+    OpenInNewTab(String),
+    OpenInNewWindow(String),
+    CopyAddress(String),
+    // url and link text, which makes for a title
+    Bookmark(String, String),
+    Save(String),
+    ViewHeader(String),
+    // a link asking for input to send to the url, with its text as the prompt
+    Prompt(String, String),
+    // the pointer moved onto a link, or off one when None
+    Hover(Option<String>),
+}
+
+// A single page in the tab strip, every tab navigates on its own and keeps its
+// own history.
+pub struct Tab {
+    pub state: RefCell<Castor>,
+    pub scroll: ScrolledWindow,
+    pub text_view: TextView,
+    // widget placed in the notebook's tab strip
+    pub tab_label: gtk::Box,
+    pub close_button: Button,
+    title: Label,
+    // the page currently shown, None until it has loaded
+    pub page: RefCell<Option<Page>>,
+    // while the source is shown, the buffer with the rendered page
+    rendered: RefCell<Option<TextBuffer>>,
+    link_numbers: Rc<RefCell<Option<LinkNumbers>>>,
+    // the load in progress, if any
+    loading: RefCell<Option<Cancel>>,
+    // what the status bar says about this tab's last or current request
+    pub status: RefCell<String>,
+}
+
+// Links numbered by Tab::toggle_link_numbers, in order, and the digits typed
+// so far to pick one
+struct LinkNumbers {
+    urls: Vec<String>,
+    typed: String,
+}
+
+// The position in text_view's buffer under the given widget coordinates
+fn iter_at(text_view: &TextView, x: f64, y: f64) -> Option<TextIter> {
+    let (x, y) =
+        text_view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+    text_view.iter_at_location(x, y)
+}
+
+// Start and end of the run of text tagged name that iter is in
+fn tag_range(iter: &TextIter, name: &str) -> Option<(TextIter, TextIter)> {
+    let tag = iter.buffer().tag_table().lookup(name)?;
+    if !iter.has_tag(&tag) {
+        return None;
+    }
+    let mut start = iter.clone();
+    if !start.starts_tag(Some(&tag)) {
+        start.backward_to_tag_toggle(Some(&tag));
+    }
+    let mut end = iter.clone();
+    end.forward_to_tag_toggle(Some(&tag));
+    Some((start, end))
+}
+
+fn has_tag(iter: &TextIter, name: &str) -> bool {
+    iter.buffer()
+        .tag_table()
+        .lookup(name)
+        .map_or(false, |tag| iter.has_tag(&tag))
+}
+
+// The value of the mark at iter whose name starts with prefix, eg. the url
+// of a link. See PageWriter::add_mark for how they're named.
+fn mark_value(iter: &TextIter, prefix: &str) -> Option<String> {
+    iter.marks().iter().find_map(|mark| {
+        let name = mark.name()?;
+        let (_, value) = name.strip_prefix(prefix)?.split_once(':')?;
+        Some(value.to_string())
+    })
+}
+
+// The url and text of the link at iter
+fn link_at(iter: &TextIter) -> Option<(String, String)> {
+    let (start, end) = tag_range(iter, "link")?;
+    let url = mark_value(&start, HREF_MARK)?;
+    let text = iter.buffer().text(&start, &end, false).to_string();
+    Some((url, text))
+}
+
+fn url_at(text_view: &TextView, x: f64, y: f64) -> Option<String> {
+    iter_at(text_view, x, y).and_then(|iter| link_at(&iter)).map(|(url, _)| url)
+}
+
+// Makes the link text in text_view clickable. Clicks that end a selection are
+// left alone so text can be selected and copied across links.
+fn connect_links(text_view: &TextView, link_tx: &Sender<LinkAction>) {
+    let click = GestureClick::builder().button(0).build();
+    click.connect_released(clone!(@strong link_tx, @weak text_view => move |gesture, _, x, y| {
+        if text_view.buffer().has_selection() {
+            return;
+        }
+        let iter = match iter_at(&text_view, x, y) {
+            Some(iter) => iter,
+            None => return,
+        };
+        let (url, text) = match link_at(&iter) {
+            Some(link) => link,
+            None => return,
+        };
+        let action = match gesture.current_button() {
+            1 if has_tag(&iter, PROMPT_TAG) => LinkAction::Prompt(url, text),
+            1 => LinkAction::Follow(url),
+            2 => LinkAction::OpenInNewTab(url),
+            _ => return,
+        };
+        link_tx.send(action).expect("Failed to send url upon click");
+    }));
+    text_view.add_controller(&click);
+
+    // runs before the text view's own context menu so links can have theirs
+    let context_click = GestureClick::builder()
+        .button(3)
+        .propagation_phase(gtk::PropagationPhase::Capture)
+        .build();
+    context_click.connect_pressed(clone!(@strong link_tx, @weak text_view => move |gesture, _, x, y| {
+        if let Some((url, text)) = iter_at(&text_view, x, y).and_then(|iter| link_at(&iter)) {
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            link_context_menu(&text_view, x, y, url, text, link_tx.clone());
+        }
+    }));
+    text_view.add_controller(&context_click);
+
+    // only tell the browser when the pointer moves onto a different link
+    let hovered = Rc::new(RefCell::new(None));
+    let motion = EventControllerMotion::new();
+    motion.connect_motion(clone!(@strong link_tx, @strong hovered, @weak text_view => move |_, x, y| {
+        let url = url_at(&text_view, x, y);
+        if *hovered.borrow() == url {
+            return;
+        }
+        let cursor = if url.is_some() { "pointer" } else { "text" };
+        text_view.set_cursor_from_name(Some(cursor));
+        hovered.replace(url.clone());
+        link_tx.send(LinkAction::Hover(url)).expect("Failed to send hovered url");
+    }));
+    motion.connect_leave(clone!(@strong link_tx, @strong hovered => move |_| {
+        if hovered.replace(None).is_some() {
+            link_tx.send(LinkAction::Hover(None)).expect("Failed to send hovered url");
+        }
+    }));
+    text_view.add_controller(&motion);
+}
+
+// Lets the number of a link be typed while the numbers are shown. The link is
+// followed as soon as no more digits could make a different choice, or on
+// enter. Escape hides the numbers again.
+fn connect_link_numbers(
+    text_view: &TextView,
+    link_tx: &Sender<LinkAction>,
+    link_numbers: &Rc<RefCell<Option<LinkNumbers>>>,
+) {
+    let keys = EventControllerKey::new();
+    keys.connect_key_pressed(clone!(@strong link_tx, @strong link_numbers, @weak text_view => @default-return gtk::Inhibit(false), move |_, key, _, _| {
+        let mut numbers = link_numbers.borrow_mut();
+        let state = match numbers.as_mut() {
+            Some(state) => state,
+            None => return gtk::Inhibit(false),
+        };
+        let chosen = if let Some(digit) = key.to_unicode().filter(char::is_ascii_digit) {
+            state.typed.push(digit);
+            let number: usize = state.typed.parse().unwrap_or(0);
+            if number * 10 <= state.urls.len() {
+                return gtk::Inhibit(true);
+            }
+            Some(number)
+        } else if key == gdk::Key::BackSpace {
+            state.typed.pop();
+            return gtk::Inhibit(true);
+        } else if key == gdk::Key::Return || key == gdk::Key::KP_Enter {
+            state.typed.parse().ok()
+        } else if key == gdk::Key::Escape {
+            None
+        } else {
+            return gtk::Inhibit(false);
+        };
+
+        let url = chosen
+            .and_then(|number: usize| number.checked_sub(1))
+            .and_then(|index| state.urls.get(index).cloned());
+        *numbers = None;
+        remove_link_numbers(&text_view.buffer());
+        if let Some(url) = url {
+            link_tx.send(LinkAction::Follow(url)).expect("Failed to send url upon click");
+        }
+        gtk::Inhibit(true)
+    }));
+    text_view.add_controller(&keys);
+}
+
+// Start and end offsets and url of every link in buffer, in order
+fn links(buffer: &TextBuffer) -> Vec<(i32, i32, String)> {
+    let mut links = Vec::new();
+    let link = match buffer.tag_table().lookup("link") {
+        Some(link) => link,
+        None => return links,
+    };
+    let mut iter = buffer.start_iter();
+    loop {
+        if iter.starts_tag(Some(&link)) {
+            if let Some(url) = mark_value(&iter, HREF_MARK) {
+                let mut end = iter.clone();
+                end.forward_to_tag_toggle(Some(&link));
+                links.push((iter.offset(), end.offset(), url));
+            }
+        }
+        if !iter.forward_to_tag_toggle(Some(&link)) {
+            break;
+        }
+    }
+    links
+}
+
+fn remove_link_numbers(buffer: &TextBuffer) {
+    let tag = match buffer.tag_table().lookup("link-number") {
+        Some(tag) => tag,
+        None => return,
+    };
+    // deleting text invalidates iterators, so find the numbers first and
+    // delete them back to front
+    let mut numbers = Vec::new();
+    let mut iter = buffer.start_iter();
+    loop {
+        if iter.starts_tag(Some(&tag)) {
+            let mut end = iter.clone();
+            end.forward_to_tag_toggle(Some(&tag));
+            numbers.push((iter.offset(), end.offset()));
+        }
+        if !iter.forward_to_tag_toggle(Some(&tag)) {
+            break;
+        }
+    }
+    for (start, end) in numbers.into_iter().rev() {
+        buffer.delete(&mut buffer.iter_at_offset(start), &mut buffer.iter_at_offset(end));
+    }
+}
+
+fn link_context_menu(
+    text_view: &TextView,
+    x: f64,
+    y: f64,
+    url: String,
+    text: String,
+    link_tx: Sender<LinkAction>,
+) {
+    let items = [
+        ("Open in new tab", LinkAction::OpenInNewTab(url.clone())),
+        ("Open in new window", LinkAction::OpenInNewWindow(url.clone())),
+        ("Copy link address", LinkAction::CopyAddress(url.clone())),
+        ("Bookmark link", LinkAction::Bookmark(url.clone(), text)),
+        ("Save link target…", LinkAction::Save(url.clone())),
+        ("View response header", LinkAction::ViewHeader(url)),
+    ];
+
+    let menu = gtk::Box::new(Orientation::Vertical, 0);
+    let popover = Popover::builder()
+        .child(&menu)
+        .has_arrow(false)
+        .pointing_to(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1))
+        .build();
+    popover.set_parent(text_view);
+    popover.connect_closed(|popover| popover.unparent());
+    for (label, action) in items {
+        let item = Button::builder().label(label).has_frame(false).build();
+        item.connect_clicked(clone!(@strong link_tx, @weak popover => move |_| {
+            link_tx.send(action.clone()).expect("Failed to send link action");
+            popover.popdown();
+        }));
+        menu.append(&item);
+    }
+    popover.popup();
+}
+
+impl Tab {
+    pub fn new(state: Castor, tag_table: &TextTagTable) -> (Tab, Receiver<LinkAction>) {
+        let text_view = TextView::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .wrap_mode(gtk::WrapMode::WordChar)
+            .editable(false)
+            .cursor_visible(false)
+            .has_tooltip(true)
+            .build();
+        text_view.set_buffer(Some(&TextBuffer::new(Some(tag_table))));
+        // the text view's own menu already has copy and select all
+        let page_menu = gio::Menu::new();
+        page_menu.append(Some("View source"), Some("win.view-source"));
+        page_menu.append(Some("Page info"), Some("win.page-info"));
+        page_menu.append(Some("Save page…"), Some("win.save-page"));
+        text_view.set_extra_menu(Some(&page_menu));
+        text_view.connect_query_tooltip(|text_view, x, y, _keyboard, tooltip| {
+            let alt_text = iter_at(text_view, x as f64, y as f64)
+                .and_then(|iter| tag_range(&iter, ALT_TEXT_TAG))
+                .and_then(|(start, _)| mark_value(&start, ALT_TEXT_MARK));
+            match alt_text {
+                Some(alt_text) => {
+                    tooltip.set_text(Some(&alt_text));
+                    true
+                }
+                None => false,
+            }
+        });
+        let scroll = ScrolledWindow::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .child(&text_view)
+            .build();
+
+        let title = Label::builder()
+            .ellipsize(pango::EllipsizeMode::End)
+            .max_width_chars(24)
+            .build();
+        let close_button = Button::builder()
+            .label("×")
+            .has_frame(false)
+            .tooltip_text("Close tab")
+            .build();
+        let tab_label = gtk::Box::new(Orientation::Horizontal, 4);
+        tab_label.append(&title);
+        tab_label.append(&close_button);
+
+        let (link_tx, link_rx) = MainContext::channel(PRIORITY_DEFAULT);
+        connect_links(&text_view, &link_tx);
+        let link_numbers = Rc::new(RefCell::new(None));
+        connect_link_numbers(&text_view, &link_tx, &link_numbers);
+        let tab = Tab {
+            state: RefCell::new(state),
+            scroll,
+            text_view,
+            tab_label,
+            close_button,
+            title,
+            page: RefCell::new(None),
+            rendered: RefCell::new(None),
+            link_numbers,
+            loading: RefCell::new(None),
+            status: RefCell::new(String::new()),
+        };
+        tab.update_title();
+        (tab, link_rx)
+    }
+
+    pub fn update_title(&self) {
+        let state = self.state.borrow();
+        let current = state.history.current();
+        let title = match (&current.title, url::Url::parse(&current.url)) {
+            (Some(title), _) => title.clone(),
+            (None, Ok(url)) => format!("{}{}", url.host_str().unwrap_or_default(), url.path()),
+            (None, Err(_)) => current.url.clone(),
+        };
+        self.title.set_text(&title);
+        self.title.set_tooltip_text(Some(&current.url));
+    }
+
+    pub fn title(&self) -> String {
+        self.title.text().to_string()
+    }
+
+    // Remembers how far down the current page is scrolled, call before
+    // navigating away from it
+    pub fn save_scroll(&self) {
+        let scroll = self.scroll.vadjustment().value();
+        self.state.borrow_mut().history.current_mut().scroll = scroll;
+    }
+
+    // Scrolls to the position saved for the current page. The text view lays
+    // out new content while idle, so this waits for that to happen first.
+    pub fn restore_scroll(&self) {
+        let scroll = self.state.borrow().history.current().scroll;
+        let adjustment = self.scroll.vadjustment();
+        glib::idle_add_local_once(move || adjustment.set_value(scroll));
+    }
+
+    // Cancels the load in progress, if any, and returns a handle for a new one
+    pub fn start_loading(&self) -> Cancel {
+        let cancel = Cancel::new();
+        if let Some(previous) = self.loading.replace(Some(cancel.clone())) {
+            previous.cancel();
+        }
+        self.status.borrow_mut().clear();
+        cancel
+    }
+
+    pub fn finish_loading(&self, cancel: &Cancel) {
+        if self.is_current_load(cancel) {
+            self.loading.replace(None);
+        }
+    }
+
+    // Whether cancel belongs to the load in progress, rather than one that
+    // was stopped or replaced
+    pub fn is_current_load(&self, cancel: &Cancel) -> bool {
+        self.loading.borrow().as_ref() == Some(cancel)
+    }
+
+    pub fn stop_loading(&self) {
+        if let Some(cancel) = self.loading.take() {
+            cancel.cancel();
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.borrow().is_some()
+    }
+
+    // Remembers the page that was just loaded into the text view
+    pub fn set_page(&self, page: Page) {
+        self.rendered.replace(None);
+        self.link_numbers.replace(None);
+        self.page.replace(Some(page));
+    }
+
+    // Switches between the rendered page and its source, with line numbers
+    pub fn toggle_source(&self) {
+        self.hide_link_numbers();
+        if let Some(rendered) = self.rendered.take() {
+            self.text_view.set_buffer(Some(&rendered));
+            return;
+        }
+        let page = self.page.borrow();
+        let page = match &*page {
+            Some(page) => page,
+            None => return,
+        };
+
+        let mime = Mime::parse(&page.response.header.meta);
+        let body = page.response.body.as_deref().unwrap_or_default();
+        let source = match mime.decode(body) {
+            Some(source) if mime.is_text() => source,
+            _ => {
+                let message = format!("Can't show the source of {} content", mime.essence);
+                message.into()
+            }
+        };
+        let buffer = TextBuffer::new(Some(&self.text_view.buffer().tag_table()));
+        let width = source.lines().count().to_string().len();
+        for (number, line) in source.lines().enumerate() {
+            let number = format!("{:>width$}  ", number + 1);
+            buffer.insert_with_tags_by_name(&mut buffer.end_iter(), &number, &["line-number"]);
+            buffer.insert_with_tags_by_name(&mut buffer.end_iter(), line, &["preformatted"]);
+            buffer.insert(&mut buffer.end_iter(), "\n");
+        }
+
+        self.rendered.replace(Some(self.text_view.buffer()));
+        self.text_view.set_buffer(Some(&buffer));
+    }
+
+    // Colours the links on the page loaded from base_url that lead somewhere
+    // already in history
+    pub fn mark_visited_links(&self, base_url: &str, history: &History) {
+        let buffer = self.text_view.buffer();
+        let visited = match buffer.tag_table().lookup("visited") {
+            Some(visited) => visited,
+            None => return,
+        };
+
+        // tagging the buffer invalidates iterators, so find the links first
+        let links: Vec<_> = links(&buffer)
+            .into_iter()
+            .filter(|(_, _, url)| {
+                resolve_url(base_url, url).map_or(false, |url| history.contains(&url))
+            })
+            .collect();
+
+        for (start, end, _) in links {
+            buffer.apply_tag(&visited, &buffer.iter_at_offset(start), &buffer.iter_at_offset(end));
+        }
+    }
+
+    // Puts a number in front of every link so it can be followed by typing
+    // the number, or takes the numbers away again
+    pub fn toggle_link_numbers(&self) {
+        if self.link_numbers.borrow().is_some() {
+            self.hide_link_numbers();
+            return;
+        }
+        let buffer = self.text_view.buffer();
+        let links = links(&buffer);
+        if links.is_empty() {
+            return;
+        }
+
+        // inserting from the back keeps the offsets of earlier links valid
+        for (i, (start, _, _)) in links.iter().enumerate().rev() {
+            let number = format!("[{}]", i + 1);
+            buffer.insert_with_tags_by_name(&mut buffer.iter_at_offset(*start), &number, &["link-number"]);
+        }
+        self.link_numbers.replace(Some(LinkNumbers {
+            urls: links.into_iter().map(|(_, _, url)| url).collect(),
+            typed: String::new(),
+        }));
+        self.text_view.grab_focus();
+    }
+
+    fn hide_link_numbers(&self) {
+        if self.link_numbers.take().is_some() {
+            remove_link_numbers(&self.text_view.buffer());
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.state.borrow().history.can_go_back()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.state.borrow().history.can_go_forward()
+    }
+}
@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use glib::{clone, MainContext};
+use gtk::{
+    prelude::*, ApplicationWindow, Button, Dialog, DialogFlags, Entry, Grid, Label, Notebook,
+    Orientation, ScrolledWindow, TextView,
+};
+use gtk4 as gtk;
+
+use crate::choose_file;
+use crate::client::Upload;
+use crate::load_page_error_modal;
+use crate::LoadPageError;
+
+// Asks what to send to the titan:// url, either text written in the dialog
+// or a file from disk. Returns None if the user cancelled.
+pub async fn upload_modal(window: &ApplicationWindow, url: &str) -> Option<Upload> {
+    let dialog = Dialog::with_buttons(
+        Some("Upload"),
+        Some(window),
+        DialogFlags::MODAL,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Upload", gtk::ResponseType::Ok),
+        ],
+    );
+    dialog.set_default_size(600, 400);
+    let content = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    dialog.content_area().append(&content);
+    content.append(
+        &Label::builder()
+            .label(&format!("Upload to {url}"))
+            .xalign(0.0)
+            .wrap(true)
+            .build(),
+    );
+
+    let text_view = TextView::builder()
+        .wrap_mode(gtk::WrapMode::WordChar)
+        .monospace(true)
+        .build();
+    let scroll = ScrolledWindow::builder()
+        .child(&text_view)
+        .vexpand(true)
+        .build();
+    let choose = Button::with_label("Choose file…");
+    let chosen = Label::builder().label("No file chosen").xalign(0.0).build();
+    let file_box = gtk::Box::new(Orientation::Horizontal, 6);
+    file_box.set_valign(gtk::Align::Start);
+    file_box.append(&choose);
+    file_box.append(&chosen);
+
+    let sources = Notebook::new();
+    sources.append_page(&scroll, Some(&Label::new(Some("Text"))));
+    sources.append_page(&file_box, Some(&Label::new(Some("File"))));
+    content.append(&sources);
+
+    let mime = Entry::builder().text("text/gemini").hexpand(true).build();
+    let token = Entry::builder()
+        .placeholder_text("Only if the capsule asks for one")
+        .build();
+    let options = Grid::builder().row_spacing(6).column_spacing(12).build();
+    options.attach(&Label::builder().label("Type").xalign(0.0).build(), 0, 0, 1, 1);
+    options.attach(&mime, 1, 0, 1, 1);
+    options.attach(&Label::builder().label("Token").xalign(0.0).build(), 0, 1, 1, 1);
+    options.attach(&token, 1, 1, 1, 1);
+    content.append(&options);
+
+    let path: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let parent = window.clone();
+    choose.connect_clicked(
+        clone!(@weak parent, @weak chosen, @weak mime, @strong path => move |_| {
+            MainContext::default().spawn_local(async move {
+                if let Some(file) = choose_file(&parent, "Select file to upload").await {
+                    chosen.set_text(&file.display().to_string());
+                    mime.set_text(guess_mime(&file));
+                    path.replace(Some(file));
+                }
+            });
+        }),
+    );
+
+    let user_response = dialog.run_future().await;
+    dialog.close();
+    if user_response != gtk::ResponseType::Ok {
+        return None;
+    }
+
+    let data = if sources.current_page() == Some(0) {
+        let buffer = text_view.buffer();
+        buffer
+            .text(&buffer.start_iter(), &buffer.end_iter(), false)
+            .to_string()
+            .into_bytes()
+    } else {
+        let path = path.borrow().clone()?;
+        match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) => {
+                let err =
+                    anyhow::Error::from(err).context(format!("Failed to read {}", path.display()));
+                load_page_error_modal(window, LoadPageError::Upload(err)).await;
+                return None;
+            }
+        }
+    };
+    let token = token.text().to_string();
+    Some(Upload {
+        mime: mime.text().trim().to_string(),
+        token: if token.is_empty() { None } else { Some(token) },
+        data,
+    })
+}
+
+// A mime type for a file going by its extension. It's only a guess, the
+// user can change it before uploading.
+fn guess_mime(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "gmi" | "gemini" => "text/gemini",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+
+// What the store knows about a certificate presented by a host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trust {
+    // the fingerprint matches the one we have on record
+    Known,
+    // we have never seen this host before
+    FirstUse,
+    // the host presented a different certificate than the one on record
+    Changed { known: String },
+}
+
+// Trust-on-first-use store mapping "host:port" to the sha256 fingerprint of
+// the certificate that host presented on our first visit.
+pub struct KnownHosts {
+    path: PathBuf,
+    hosts: HashMap<String, String>,
+    // fingerprints the user accepted for this session only, these are never
+    // written to disk
+    session: HashMap<String, String>,
+}
+
+impl KnownHosts {
+    // Loads the store from the known_hosts file in the castor config
+    // directory, a missing file is treated as an empty store.
+    pub fn load() -> Result<KnownHosts> {
+        let path = crate::config_dir().join("known_hosts");
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let mut hosts = HashMap::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((host, fingerprint)) = line.split_once(' ') {
+                hosts.insert(host.to_string(), fingerprint.trim().to_string());
+            }
+        }
+
+        Ok(KnownHosts {
+            path,
+            hosts,
+            session: HashMap::new(),
+        })
+    }
+
+    pub fn check(&self, host: &str, fingerprint: &str) -> Trust {
+        if self.session.get(host).map(String::as_str) == Some(fingerprint) {
+            return Trust::Known;
+        }
+        match self.hosts.get(host) {
+            Some(known) if known == fingerprint => Trust::Known,
+            Some(known) => Trust::Changed {
+                known: known.clone(),
+            },
+            None => Trust::FirstUse,
+        }
+    }
+
+    // Accepts a fingerprint until castor exits, the stored one is left alone
+    pub fn trust_once(&mut self, host: &str, fingerprint: &str) {
+        self.session
+            .insert(host.to_string(), fingerprint.to_string());
+    }
+
+    // Records a fingerprint as the trusted one for host and saves the store
+    pub fn trust_permanently(&mut self, host: &str, fingerprint: &str) -> Result<()> {
+        self.session.remove(host);
+        self.hosts.insert(host.to_string(), fingerprint.to_string());
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = &self.path;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        let mut hosts: Vec<_> = self.hosts.iter().collect();
+        hosts.sort();
+        let mut contents = String::new();
+        for (host, fingerprint) in hosts {
+            contents += &format!("{host} {fingerprint}\n");
+        }
+        fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+pub fn fingerprint(certificate: &Certificate) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, &certificate.0);
+    digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// A certificate that didn't match what the store has on record for a host
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub host: String,
+    pub known: String,
+    pub presented: String,
+}
+
+// rustls verifier backed by the known hosts store. Gemini capsules mostly use
+// self signed certificates so no chain validation is done, instead the
+// fingerprint is compared against the one on record.
+pub struct TofuVerifier {
+    known_hosts: Arc<Mutex<KnownHosts>>,
+    host: String,
+    // set when the handshake was rejected because the certificate changed so
+    // the caller can tell that apart from other handshake failures
+    mismatch: Arc<Mutex<Option<Mismatch>>>,
+}
+
+impl TofuVerifier {
+    pub fn new(
+        known_hosts: Arc<Mutex<KnownHosts>>,
+        host: String,
+        mismatch: Arc<Mutex<Option<Mismatch>>>,
+    ) -> TofuVerifier {
+        TofuVerifier {
+            known_hosts,
+            host,
+            mismatch,
+        }
+    }
+}
+
+impl ServerCertVerifier for TofuVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let presented = fingerprint(end_entity);
+        let mut known_hosts = self.known_hosts.lock().unwrap();
+        match known_hosts.check(&self.host, &presented) {
+            Trust::Known => Ok(ServerCertVerified::assertion()),
+            Trust::FirstUse => {
+                if let Err(err) = known_hosts.trust_permanently(&self.host, &presented) {
+                    eprintln!("Failed to save known hosts: {err}");
+                }
+                Ok(ServerCertVerified::assertion())
+            }
+            Trust::Changed { known } => {
+                *self.mismatch.lock().unwrap() = Some(Mismatch {
+                    host: self.host.clone(),
+                    known,
+                    presented,
+                });
+                Err(rustls::Error::General(String::from(
+                    "Certificate doesn't match the one on record",
+                )))
+            }
+        }
+    }
+}